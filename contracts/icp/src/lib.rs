@@ -1,32 +1,256 @@
+use bech32::{u5, FromBase32, ToBase32, Variant};
+use candid::{CandidType, Decode, Encode, Nat, Principal};
 use ic_cdk::api::caller;
+use ic_cdk::api::management_canister::bitcoin::{
+    bitcoin_get_current_fee_percentiles, bitcoin_get_utxos, bitcoin_send_transaction, BitcoinNetwork,
+    GetCurrentFeePercentilesRequest, GetUtxosRequest, SendTransactionRequest, Utxo, UtxoFilter,
+};
+use ic_cdk::api::management_canister::ecdsa::{
+    ecdsa_public_key, sign_with_ecdsa, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument, SignWithEcdsaArgument,
+};
 use ic_cdk_macros::*;
+use ic_cdk_timers::TimerId;
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell, Storable};
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::time::Duration;
+use ripemd::Ripemd160;
+use satsuma_messages::attribution::{self, YieldShare};
+use satsuma_messages::{Envelope, IcpToInjective, InjectiveExecuteMsg, InjectiveToIcp};
 use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+// Default minimum for conversion: 100 USDC
+const CONVERSION_THRESHOLD: u128 = 100_000_000;
+const DEFAULT_CONVERSION_INTERVAL_SECONDS: u64 = 60 * 60;
+const DEFAULT_MAX_DELIVERY_ATTEMPTS: u32 = 5;
+// Conversions refuse prices older than this
+const MAX_PRICE_AGE_SECONDS: u64 = 60 * 60;
+// Cycles attached to every XRC `get_exchange_rate` call
+const XRC_CALL_CYCLES: u128 = 1_000_000_000;
+const SATOSHIS_PER_BTC: u128 = 100_000_000;
+const MICROS_PER_USD: u128 = 1_000_000;
+// Fixed-point scale of the reward-per-share index
+const REWARD_INDEX_SCALE: u128 = 1_000_000_000_000_000_000;
+// Satoshis below which an output is non-standard; smaller change is left as fee
+const BTC_DUST_LIMIT_SATS: u64 = 546;
+const SIGHASH_ALL: u32 = 1;
+// secp256k1 group order, to keep signatures in low-S form
+const SECP256K1_ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+// Fee rate when the network reports no percentiles (e.g. regtest), and the relay minimum
+const DEFAULT_FEE_MSAT_PER_VBYTE: u64 = 2_000;
+const MIN_FEE_MSAT_PER_VBYTE: u64 = 1_000;
+
+// Stable memory layout. Bump LAYOUT_VERSION and migrate in `post_upgrade`
+// whenever a region changes shape; never reuse a MemoryId.
+const LAYOUT_VERSION: u32 = 3;
+const LAYOUT_VERSION_MEMORY_ID: MemoryId = MemoryId::new(0);
+const BALANCES_MEMORY_ID: MemoryId = MemoryId::new(1);
+const BITCOIN_BALANCES_MEMORY_ID: MemoryId = MemoryId::new(2);
+const INJECTIVE_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(3);
+const YIELD_ACCUMULATOR_MEMORY_ID: MemoryId = MemoryId::new(4);
+const TOTAL_BITCOIN_CONVERTED_MEMORY_ID: MemoryId = MemoryId::new(5);
+const ADMINS_MEMORY_ID: MemoryId = MemoryId::new(6);
+const PENDING_ADMIN_HANDOVER_MEMORY_ID: MemoryId = MemoryId::new(7);
+const BITCOIN_PRICE_MEMORY_ID: MemoryId = MemoryId::new(8);
+const CKBTC_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(9);
+const CKBTC_RECONCILIATION_MEMORY_ID: MemoryId = MemoryId::new(10);
+const BTC_RETRIEVALS_MEMORY_ID: MemoryId = MemoryId::new(11);
+const NATIVE_BTC_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(12);
+const DEPOSIT_ADDRESSES_MEMORY_ID: MemoryId = MemoryId::new(13);
+const CREDITED_UTXOS_MEMORY_ID: MemoryId = MemoryId::new(14);
+const NATIVE_BTC_HELD_MEMORY_ID: MemoryId = MemoryId::new(15);
+const REWARD_STATE_MEMORY_ID: MemoryId = MemoryId::new(16);
+const REWARD_CHECKPOINTS_MEMORY_ID: MemoryId = MemoryId::new(17);
+const SCHEDULE_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(18);
+const SCHEDULE_STATUS_MEMORY_ID: MemoryId = MemoryId::new(19);
+const TRUSTED_BRIDGES_MEMORY_ID: MemoryId = MemoryId::new(20);
+const PROCESSED_MESSAGES_MEMORY_ID: MemoryId = MemoryId::new(21);
+const BRIDGE_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(22);
+const OUTBOUND_QUEUE_MEMORY_ID: MemoryId = MemoryId::new(23);
+const OUTBOUND_NONCE_MEMORY_ID: MemoryId = MemoryId::new(24);
+const TRANSPORT_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(25);
+const YIELD_ATTRIBUTIONS_MEMORY_ID: MemoryId = MemoryId::new(26);
+const CKBTC_RESERVE_MEMORY_ID: MemoryId = MemoryId::new(27);
+const NATIVE_BTC_BALANCES_MEMORY_ID: MemoryId = MemoryId::new(28);
+const SPENT_UTXOS_MEMORY_ID: MemoryId = MemoryId::new(29);
 
 thread_local! {
-    static BALANCES: RefCell<HashMap<String, u128>> = RefCell::new(HashMap::new());
-    static BITCOIN_BALANCES: RefCell<HashMap<String, u64>> = RefCell::new(HashMap::new());
-    static INJECTIVE_CONFIG: RefCell<InjectiveConfig> = RefCell::new(InjectiveConfig::default());
-    static YIELD_ACCUMULATOR: RefCell<u128> = RefCell::new(0);
-    static TOTAL_BITCOIN_CONVERTED: RefCell<u64> = RefCell::new(0);
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+
+    static STORED_LAYOUT_VERSION: RefCell<StableCell<u32, Memory>> = RefCell::new(
+        StableCell::init(memory(LAYOUT_VERSION_MEMORY_ID), 0)
+            .expect("failed to init layout version")
+    );
+    static BALANCES: RefCell<StableBTreeMap<String, u128, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(BALANCES_MEMORY_ID))
+    );
+    static BITCOIN_BALANCES: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(BITCOIN_BALANCES_MEMORY_ID))
+    );
+    static INJECTIVE_CONFIG: RefCell<StableCell<InjectiveConfig, Memory>> = RefCell::new(
+        StableCell::init(memory(INJECTIVE_CONFIG_MEMORY_ID), InjectiveConfig::default())
+            .expect("failed to init injective config")
+    );
+    static YIELD_ACCUMULATOR: RefCell<StableCell<u128, Memory>> = RefCell::new(
+        StableCell::init(memory(YIELD_ACCUMULATOR_MEMORY_ID), 0)
+            .expect("failed to init yield accumulator")
+    );
+    static TOTAL_BITCOIN_CONVERTED: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(memory(TOTAL_BITCOIN_CONVERTED_MEMORY_ID), 0)
+            .expect("failed to init total bitcoin converted")
+    );
+    // Admin principal -> time (ns) it was added
+    static ADMINS: RefCell<StableBTreeMap<Principal, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(ADMINS_MEMORY_ID))
+    );
+    static PENDING_ADMIN_HANDOVER: RefCell<StableCell<PendingAdminHandover, Memory>> = RefCell::new(
+        StableCell::init(memory(PENDING_ADMIN_HANDOVER_MEMORY_ID), PendingAdminHandover::default())
+            .expect("failed to init pending admin handover")
+    );
+    static BITCOIN_PRICE: RefCell<StableCell<StoredBitcoinPrice, Memory>> = RefCell::new(
+        StableCell::init(memory(BITCOIN_PRICE_MEMORY_ID), StoredBitcoinPrice::default())
+            .expect("failed to init bitcoin price")
+    );
+    static CKBTC_CONFIG: RefCell<StableCell<CkbtcConfig, Memory>> = RefCell::new(
+        StableCell::init(memory(CKBTC_CONFIG_MEMORY_ID), CkbtcConfig::default())
+            .expect("failed to init ckbtc config")
+    );
+    static CKBTC_RECONCILIATION: RefCell<StableCell<StoredReconciliation, Memory>> = RefCell::new(
+        StableCell::init(memory(CKBTC_RECONCILIATION_MEMORY_ID), StoredReconciliation::default())
+            .expect("failed to init ckbtc reconciliation")
+    );
+    // ckBTC burn block index -> native BTC retrieval
+    static BTC_RETRIEVALS: RefCell<StableBTreeMap<u64, BtcRetrieval, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(BTC_RETRIEVALS_MEMORY_ID))
+    );
+    static NATIVE_BTC_CONFIG: RefCell<StableCell<NativeBtcConfig, Memory>> = RefCell::new(
+        StableCell::init(memory(NATIVE_BTC_CONFIG_MEMORY_ID), NativeBtcConfig::default())
+            .expect("failed to init native btc config")
+    );
+    // Principal -> P2WPKH deposit address
+    static DEPOSIT_ADDRESSES: RefCell<StableBTreeMap<String, String, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(DEPOSIT_ADDRESSES_MEMORY_ID))
+    );
+    // "txid:vout" -> satoshis credited, so each UTXO is credited once
+    static CREDITED_UTXOS: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(CREDITED_UTXOS_MEMORY_ID))
+    );
+    // Satoshis credited from native deposits, held at canister addresses rather than as ckBTC
+    static NATIVE_BTC_HELD: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(memory(NATIVE_BTC_HELD_MEMORY_ID), 0)
+            .expect("failed to init native btc held")
+    );
+    static REWARD_STATE: RefCell<StableCell<RewardState, Memory>> = RefCell::new(
+        StableCell::init(memory(REWARD_STATE_MEMORY_ID), RewardState::default())
+            .expect("failed to init reward state")
+    );
+    // Principal -> reward index at the last settlement
+    static REWARD_CHECKPOINTS: RefCell<StableBTreeMap<String, RewardCheckpoint, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(REWARD_CHECKPOINTS_MEMORY_ID))
+    );
+    static SCHEDULE_CONFIG: RefCell<StableCell<ScheduleConfig, Memory>> = RefCell::new(
+        StableCell::init(memory(SCHEDULE_CONFIG_MEMORY_ID), ScheduleConfig::default())
+            .expect("failed to init schedule config")
+    );
+    static SCHEDULE_STATUS: RefCell<StableCell<ScheduleStatus, Memory>> = RefCell::new(
+        StableCell::init(memory(SCHEDULE_STATUS_MEMORY_ID), ScheduleStatus::default())
+            .expect("failed to init schedule status")
+    );
+    // Bridge principal -> time (ns) it was trusted
+    static TRUSTED_BRIDGES: RefCell<StableBTreeMap<Principal, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(TRUSTED_BRIDGES_MEMORY_ID))
+    );
+    // Inbound message id -> time (ns) it was processed
+    static PROCESSED_MESSAGES: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(PROCESSED_MESSAGES_MEMORY_ID))
+    );
+    static BRIDGE_CONFIG: RefCell<StableCell<BridgeConfig, Memory>> = RefCell::new(
+        StableCell::init(memory(BRIDGE_CONFIG_MEMORY_ID), BridgeConfig::default())
+            .expect("failed to init bridge config")
+    );
+    // Nonce -> outbound message to Injective
+    static OUTBOUND_QUEUE: RefCell<StableBTreeMap<u64, OutboundMessage, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(OUTBOUND_QUEUE_MEMORY_ID))
+    );
+    static OUTBOUND_NONCE: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(memory(OUTBOUND_NONCE_MEMORY_ID), 0)
+            .expect("failed to init outbound nonce")
+    );
+    static TRANSPORT_CONFIG: RefCell<StableCell<TransportConfig, Memory>> = RefCell::new(
+        StableCell::init(memory(TRANSPORT_CONFIG_MEMORY_ID), TransportConfig::default())
+            .expect("failed to init transport config")
+    );
+    // Per-depositor attribution roots of bridged yield, keyed by envelope nonce
+    static YIELD_ATTRIBUTIONS: RefCell<StableBTreeMap<u64, YieldAttribution, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(YIELD_ATTRIBUTIONS_MEMORY_ID))
+    );
+    // Satoshis of ckBTC on the ledger set aside for conversions but not yet credited to anyone
+    static CKBTC_RESERVE: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(memory(CKBTC_RESERVE_MEMORY_ID), 0)
+            .expect("failed to init ckbtc reserve")
+    );
+    // Principal -> satoshis credited from native deposits. Kept apart from `BITCOIN_BALANCES`
+    // because no ckBTC backs them; they leave through `withdraw_native_btc` instead.
+    static NATIVE_BTC_BALANCES: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(NATIVE_BTC_BALANCES_MEMORY_ID))
+    );
+    // "txid:vout" of credited UTXOs spent by native withdrawals -> time (ns) they were spent
+    static SPENT_UTXOS: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(SPENT_UTXOS_MEMORY_ID))
+    );
+
+    // Timers do not survive upgrades; `arm_timers` recreates them from SCHEDULE_CONFIG
+    static TIMERS: RefCell<Vec<TimerId>> = RefCell::new(Vec::new());
+    // In-memory transport for tests (`mock-transport` feature); deliveries are lost on upgrade
+    #[cfg(feature = "mock-transport")]
+    static MOCK_DELIVERIES: RefCell<Vec<OutboundMessage>> = RefCell::new(Vec::new());
+    #[cfg(feature = "mock-transport")]
+    static MOCK_TRANSPORT_FAILING: RefCell<bool> = RefCell::new(false);
+}
+
+fn memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(id))
+}
+
+// Error returned by every canister endpoint
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SatsumaError {
+    Unauthorized,
+    InsufficientBalance { required: u128, available: u128 },
+    StalePrice { updated_at: u64, max_age_seconds: u64 },
+    BelowThreshold { amount: u128, threshold: u128 },
+    Paused,
+    BridgeError(String),
+    DuplicateMessage(String),
+    OracleError(String),
+    LedgerError(String),
+    InvalidArgument(String),
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct BalanceResponse {
     pub principal: String,
     pub balance: u128,
     pub bitcoin_balance: u64,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct BitcoinBalanceResponse {
     pub principal: String,
     pub bitcoin_balance: u64,
     pub usd_value: u128,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct InjectiveConfig {
     pub contract_address: String,
     pub axelar_gateway: String,
@@ -45,291 +269,2696 @@ impl Default for InjectiveConfig {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct CrossChainMessage {
-    pub action: String,
-    pub user: String,
-    pub amount: u128,
-    pub recipient: Option<String>,
+// Stores a candid-encodable type in stable memory
+macro_rules! impl_candid_storable {
+    ($t:ty) => {
+        impl Storable for $t {
+            fn to_bytes(&self) -> Cow<[u8]> {
+                Cow::Owned(Encode!(self).expect(concat!("failed to encode ", stringify!($t))))
+            }
+
+            fn from_bytes(bytes: Cow<[u8]>) -> Self {
+                Decode!(bytes.as_ref(), Self).expect(concat!("failed to decode ", stringify!($t)))
+            }
+
+            const BOUND: Bound = Bound::Unbounded;
+        }
+    };
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct BitcoinConversionRequest {
+impl_candid_storable!(InjectiveConfig);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct InitArgs {
+    pub admin: Principal,
+}
+
+// Two-step admin handover: `from` proposes, `to` accepts
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PendingAdminHandover {
+    pub handover: Option<AdminHandover>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AdminHandover {
+    pub from: Principal,
+    pub to: Principal,
+}
+
+impl_candid_storable!(PendingAdminHandover);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PriceSource {
+    Manual,
+    ExchangeRateCanister,
+}
+
+// BTC/USD price in micro-dollars (6 decimals, same as USDC)
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BitcoinPrice {
+    pub price_usd_micros: u128,
+    pub updated_at: u64, // seconds
+    pub source: PriceSource,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StoredBitcoinPrice {
+    pub price: Option<BitcoinPrice>,
+}
+
+impl_candid_storable!(StoredBitcoinPrice);
+
+// Exchange Rate Canister (XRC) interface, limited to what the canister uses.
+// Any canister exposing the same `get_exchange_rate` method can stand in for
+// the XRC, e.g. a mock in local tests.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum AssetClass {
+    Cryptocurrency,
+    FiatCurrency,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Asset {
+    pub symbol: String,
+    pub class: AssetClass,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GetExchangeRateRequest {
+    pub base_asset: Asset,
+    pub quote_asset: Asset,
+    pub timestamp: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ExchangeRateMetadata {
+    pub decimals: u32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ExchangeRate {
+    pub base_asset: Asset,
+    pub quote_asset: Asset,
+    pub timestamp: u64,
+    pub rate: u64,
+    pub metadata: ExchangeRateMetadata,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct OtherError {
+    pub code: u32,
+    pub description: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ExchangeRateError {
+    AnonymousPrincipalNotAllowed,
+    Pending,
+    CryptoBaseAssetNotFound,
+    CryptoQuoteAssetNotFound,
+    StablecoinRateNotFound,
+    StablecoinRateTooFewRates,
+    StablecoinRateZeroRate,
+    ForexInvalidTimestamp,
+    ForexBaseAssetNotFound,
+    ForexQuoteAssetNotFound,
+    ForexAssetsNotFound,
+    RateLimited,
+    NotEnoughCycles,
+    FailedToAcceptCycles,
+    InconsistentRatesReceived,
+    Other(OtherError),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum GetExchangeRateResult {
+    Ok(ExchangeRate),
+    Err(ExchangeRateError),
+}
+
+// `BITCOIN_BALANCES` are claims on ckBTC held by this canister on the ckBTC ledger
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CkbtcConfig {
+    pub ledger: Option<Principal>,
+    pub minter: Option<Principal>,
+}
+
+impl_candid_storable!(CkbtcConfig);
+
+// Ledger holdings vs the sum of credited `BITCOIN_BALANCES` plus the conversion reserve, in
+// satoshis. Native deposits live in `NATIVE_BTC_BALANCES` and are not part of the comparison.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CkbtcReconciliation {
+    pub ledger_balance: u64,
+    pub credited_total: u64,
+    pub surplus: u64,
+    pub deficit: u64,
+    pub checked_at: u64, // seconds
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StoredReconciliation {
+    pub last: Option<CkbtcReconciliation>,
+}
+
+impl_candid_storable!(StoredReconciliation);
+
+// ICRC-1 ledger interface, limited to what the canister uses
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferArg {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    TemporarilyUnavailable,
+    Duplicate { duplicate_of: Nat },
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ApproveArgs {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub spender: Account,
+    pub amount: Nat,
+    pub expected_allowance: Option<Nat>,
+    pub expires_at: Option<u64>,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ApproveError {
+    BadFee { expected_fee: Nat },
+    InsufficientFunds { balance: Nat },
+    AllowanceChanged { current_allowance: Nat },
+    Expired { ledger_time: u64 },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// ckBTC minter interface, limited to what the canister uses
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RetrieveBtcWithApprovalArgs {
+    pub address: String,
+    pub amount: u64,
+    pub from_subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RetrieveBtcOk {
+    pub block_index: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum RetrieveBtcWithApprovalError {
+    MalformedAddress(String),
+    AlreadyProcessing,
+    AmountTooLow(u64),
+    InsufficientFunds { balance: u64 },
+    InsufficientAllowance { allowance: u64 },
+    TemporarilyUnavailable(String),
+    GenericError { error_message: String, error_code: u64 },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RetrieveBtcStatusRequest {
+    pub block_index: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ReimbursedAmount {
+    pub amount: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum RetrieveBtcStatusV2 {
+    Unknown,
+    Pending,
+    Signing,
+    Sending { txid: Vec<u8> },
+    Submitted { txid: Vec<u8> },
+    AmountTooLow,
+    Confirmed { txid: Vec<u8> },
+    Reimbursed(ReimbursedAmount),
+    WillReimburse(ReimbursedAmount),
+}
+
+// Native BTC withdrawal, keyed by the ckBTC burn block index
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BtcRetrieval {
+    pub block_index: u64,
     pub principal: String,
-    pub usdc_amount: u128,
+    pub address: String,
+    pub amount: u64,
+    pub status: RetrieveBtcStatusV2,
+    pub refunded: bool,
+    pub created_at: u64, // seconds
+    pub updated_at: u64, // seconds
 }
 
-// Yield management functions
-#[update]
-pub fn deposit_yield(amount: u128) {
-    let caller_id = caller().to_text();
-    BALANCES.with(|b| {
-        let mut map = b.borrow_mut();
-        let entry = map.entry(caller_id.clone()).or_insert(0);
-        *entry += amount;
-    });
-    
-    // Accumulate yield for Bitcoin conversion
-    YIELD_ACCUMULATOR.with(|acc| {
-        let mut total = acc.borrow_mut();
-        *total += amount;
-    });
-    
-    // Auto-convert to Bitcoin if threshold met
-    convert_yield_to_bitcoin();
+impl_candid_storable!(BtcRetrieval);
+
+// Native BTC deposits to per-principal P2WPKH addresses derived with threshold ECDSA
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct NativeBtcConfig {
+    pub network: BitcoinNetwork,
+    pub ecdsa_key_name: String,
+    pub min_confirmations: u32,
 }
 
-#[update]
-pub fn convert_yield_to_bitcoin() {
-    YIELD_ACCUMULATOR.with(|acc| {
-        let mut total = acc.borrow_mut();
-        if *total >= 100_000_000 { // 100 USDC minimum for conversion
-            let conversion_amount = *total;
-            *total = 0;
-            
-            // Convert to Bitcoin (simplified - in production would use price oracle)
-            let bitcoin_amount = convert_usdc_to_bitcoin(conversion_amount);
-            
-            // Distribute Bitcoin to all users proportionally
-            distribute_bitcoin_to_users(bitcoin_amount, conversion_amount);
-            
-            // Update total Bitcoin converted
-            TOTAL_BITCOIN_CONVERTED.with(|total_btc| {
-                let mut btc = total_btc.borrow_mut();
-                *btc += bitcoin_amount;
-            });
+impl Default for NativeBtcConfig {
+    fn default() -> Self {
+        Self {
+            network: BitcoinNetwork::Regtest,
+            ecdsa_key_name: "dfx_test_key".to_string(),
+            min_confirmations: 6,
         }
-    });
+    }
 }
 
-#[update]
-pub fn manual_bitcoin_conversion(principal: String, usdc_amount: u128) {
-    let caller_id = caller().to_text();
-    if caller_id != principal {
-        return; // Only allow self-conversion
+impl_candid_storable!(NativeBtcConfig);
+
+// Converted BTC is distributed pro rata to `BALANCES` (the shares) by bumping a
+// global index; users settle lazily against their checkpoint. Amounts scaled by
+// REWARD_INDEX_SCALE.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RewardState {
+    pub btc_per_share: u128,
+    pub total_shares: u128,
+    // Scaled satoshis not yet reflected in the index (rounding or no shares)
+    pub dust: u128,
+}
+
+impl_candid_storable!(RewardState);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RewardCheckpoint {
+    pub btc_per_share: u128,
+    // Scaled satoshis owed below one satoshi
+    pub remainder: u128,
+}
+
+impl_candid_storable!(RewardCheckpoint);
+
+// Periodic jobs; an interval of 0 disables the job
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ScheduleConfig {
+    pub conversion_interval_seconds: u64,
+    pub conversion_threshold: u128,
+    pub skim_interval_seconds: u64,
+    pub skim_recipient: String,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            conversion_interval_seconds: DEFAULT_CONVERSION_INTERVAL_SECONDS,
+            conversion_threshold: CONVERSION_THRESHOLD,
+            skim_interval_seconds: 0,
+            skim_recipient: String::new(),
+        }
     }
-    
-    // Check if user has enough yield balance
-    let user_balance = BALANCES.with(|b| {
-        let map = b.borrow();
-        map.get(&principal).cloned().unwrap_or(0)
-    });
-    
-    if user_balance < usdc_amount {
-        return;
+}
+
+impl_candid_storable!(ScheduleConfig);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ConversionRun {
+    pub ran_at: u64, // seconds
+    pub result: Result<u64, SatsumaError>,
+    // Set when the pre-conversion XRC refresh failed and the cached price was used
+    pub price_refresh_error: Option<SatsumaError>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SkimRun {
+    pub ran_at: u64, // seconds
+    pub result: Result<(), SatsumaError>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ScheduleStatus {
+    pub last_conversion: Option<ConversionRun>,
+    pub last_skim: Option<SkimRun>,
+    pub next_conversion_at: Option<u64>, // seconds
+    pub next_skim_at: Option<u64>,       // seconds
+}
+
+impl_candid_storable!(ScheduleStatus);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ScheduleResponse {
+    pub config: ScheduleConfig,
+    pub status: ScheduleStatus,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BtcDepositSync {
+    pub address: String,
+    pub credited_utxos: u32,
+    pub credited_amount: u64,
+    pub tip_height: u32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct NativeBtcWithdrawal {
+    /// Display (byte-reversed) hex
+    pub txid: String,
+    pub destination: String,
+    pub amount: u64,
+    /// Network fee, debited on top of `amount`
+    pub fee: u64,
+    /// Returned to the caller's deposit address and still part of their balance
+    pub change: u64,
+}
+
+// Input spending a P2WPKH output
+#[derive(Clone, Debug)]
+struct TxInput {
+    txid: Vec<u8>,
+    vout: u32,
+    value: u64,
+    sequence: u32,
+}
+
+#[derive(Clone, Debug)]
+struct TxOutput {
+    value: u64,
+    script_pubkey: Vec<u8>,
+}
+
+// Bitcoin transaction before its witnesses are attached
+#[derive(Clone, Debug)]
+struct UnsignedTx {
+    version: u32,
+    inputs: Vec<TxInput>,
+    outputs: Vec<TxOutput>,
+    lock_time: u32,
+}
+
+impl UnsignedTx {
+    fn write_outputs(&self, buf: &mut Vec<u8>) {
+        for output in &self.outputs {
+            buf.extend(output.value.to_le_bytes());
+            write_varint(buf, output.script_pubkey.len());
+            buf.extend(&output.script_pubkey);
+        }
     }
     
-    // Deduct from user's yield balance
-    BALANCES.with(|b| {
-        let mut map = b.borrow_mut();
-        if let Some(balance) = map.get_mut(&principal) {
-            *balance -= usdc_amount;
+    // Segwit serialization with one witness stack per input, or the legacy form without
+    fn serialize(&self, witnesses: Option<&[Vec<Vec<u8>>]>) -> Vec<u8> {
+        let mut buf = self.version.to_le_bytes().to_vec();
+        if witnesses.is_some() {
+            buf.extend([0x00, 0x01]);
         }
-    });
-    
-    // Convert to Bitcoin
-    let bitcoin_amount = convert_usdc_to_bitcoin(usdc_amount);
+        write_varint(&mut buf, self.inputs.len());
+        for input in &self.inputs {
+            buf.extend(&input.txid);
+            buf.extend(input.vout.to_le_bytes());
+            // Empty scriptSig; segwit inputs carry their signature in the witness
+            buf.push(0x00);
+            buf.extend(input.sequence.to_le_bytes());
+        }
+        write_varint(&mut buf, self.outputs.len());
+        self.write_outputs(&mut buf);
+        for stack in witnesses.unwrap_or_default() {
+            write_varint(&mut buf, stack.len());
+            for item in stack {
+                write_varint(&mut buf, item.len());
+                buf.extend(item);
+            }
+        }
+        buf.extend(self.lock_time.to_le_bytes());
+        buf
+    }
     
-    // Add to user's Bitcoin balance
-    BITCOIN_BALANCES.with(|btc| {
-        let mut map = btc.borrow_mut();
-        let entry = map.entry(principal).or_insert(0);
-        *entry += bitcoin_amount;
-    });
+    // In internal byte order, as the Bitcoin API reports outpoints
+    fn txid(&self) -> Vec<u8> {
+        sha256d(&self.serialize(None))
+    }
     
-    // Update total Bitcoin converted
-    TOTAL_BITCOIN_CONVERTED.with(|total_btc| {
-        let mut btc = total_btc.borrow_mut();
-        *btc += bitcoin_amount;
-    });
+    // BIP143 SIGHASH_ALL digest for input `index`, which spends a P2WPKH output of `pubkey_hash`
+    fn p2wpkh_sighash(&self, index: usize, pubkey_hash: &[u8]) -> Vec<u8> {
+        let mut prevouts = Vec::new();
+        let mut sequences = Vec::new();
+        for input in &self.inputs {
+            prevouts.extend(&input.txid);
+            prevouts.extend(input.vout.to_le_bytes());
+            sequences.extend(input.sequence.to_le_bytes());
+        }
+        let mut outputs = Vec::new();
+        self.write_outputs(&mut outputs);
+        
+        let input = &self.inputs[index];
+        let mut preimage = self.version.to_le_bytes().to_vec();
+        preimage.extend(sha256d(&prevouts));
+        preimage.extend(sha256d(&sequences));
+        preimage.extend(&input.txid);
+        preimage.extend(input.vout.to_le_bytes());
+        // scriptCode: OP_DUP OP_HASH160 <pubkey_hash> OP_EQUALVERIFY OP_CHECKSIG
+        preimage.extend([0x19, 0x76, 0xa9, 0x14]);
+        preimage.extend(pubkey_hash);
+        preimage.extend([0x88, 0xac]);
+        preimage.extend(input.value.to_le_bytes());
+        preimage.extend(input.sequence.to_le_bytes());
+        preimage.extend(sha256d(&outputs));
+        preimage.extend(self.lock_time.to_le_bytes());
+        preimage.extend(SIGHASH_ALL.to_le_bytes());
+        sha256d(&preimage)
+    }
 }
 
-// Injective management functions
-#[update]
-pub fn set_injective_config(config: InjectiveConfig) {
-    let caller_id = caller().to_text();
-    // Only allow authorized principals to set config
-    if caller_id != "admin_principal_here" {
-        return;
-    }
-    
-    INJECTIVE_CONFIG.with(|cfg| {
-        let mut config_ref = cfg.borrow_mut();
-        *config_ref = config;
-    });
+// Stringly outbound message from layout 2, kept only to migrate the outbound queue
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CrossChainMessage {
+    pub action: String,
+    pub user: String,
+    pub amount: u128,
+    pub recipient: Option<String>,
 }
 
-#[update]
-pub fn execute_injective_deposit(user: String, amount: u128) {
-    let caller_id = caller().to_text();
-    // Verify this is a cross-chain message from Injective
-    if !is_valid_cross_chain_caller(&caller_id) {
-        return;
+impl CrossChainMessage {
+    fn into_icp_message(self) -> Option<IcpToInjective> {
+        match self.action.as_str() {
+            "deposit" => Some(IcpToInjective::Deposit {
+                user: self.user,
+                amount: self.amount,
+            }),
+            "skim_yield" => Some(IcpToInjective::SkimYield {
+                recipient: self.recipient?,
+                max_slippage_bps: None,
+            }),
+            _ => None,
+        }
     }
-    
-    // Update user's balance
-    BALANCES.with(|b| {
-        let mut map = b.borrow_mut();
-        let entry = map.entry(user.clone()).or_insert(0);
-        *entry += amount;
-    });
-    
-    // Trigger yield skimming on Injective
-    trigger_injective_yield_skim(&user);
 }
 
-#[update]
-pub fn trigger_injective_yield_skim(recipient: &str) {
-    // This would send a cross-chain message to Injective to trigger yield skimming
-    // In a real implementation, this would use Axelar GMP or similar
-    let message = CrossChainMessage {
-        action: "skim_yield".to_string(),
-        user: "".to_string(),
-        amount: 0,
-        recipient: Some(recipient.to_string()),
-    };
-    
-    // Send message to Injective (placeholder)
-    send_cross_chain_message("injective", message);
+// Provenance of an inbound cross-chain message, checked by `verify_inbound_message`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct InboundEnvelope {
+    pub source_chain: String,
+    pub source_address: String, // the Injective vault contract
+    pub nonce: u64,
 }
 
-// Query functions
-#[query]
-pub fn get_balance(principal: String) -> BalanceResponse {
-    let balance = BALANCES.with(|b| {
-        let map = b.borrow();
-        map.get(&principal).cloned().unwrap_or(0)
-    });
-    
-    let bitcoin_balance = BITCOIN_BALANCES.with(|btc| {
-        let map = btc.borrow();
-        map.get(&principal).cloned().unwrap_or(0)
-    });
-    
-    BalanceResponse { 
-        principal, 
-        balance, 
-        bitcoin_balance 
+impl InboundEnvelope {
+    pub fn message_id(&self) -> String {
+        format!("{}:{}:{}", self.source_chain, self.source_address, self.nonce)
     }
 }
 
-#[query]
-pub fn my_balance() -> BalanceResponse {
-    let caller_id = caller().to_text();
-    get_balance(caller_id)
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BridgeConfig {
+    pub source_chain: String,
 }
 
-#[query]
-pub fn get_bitcoin_balance(principal: String) -> BitcoinBalanceResponse {
-    let bitcoin_balance = BITCOIN_BALANCES.with(|btc| {
-        let map = btc.borrow();
-        map.get(&principal).cloned().unwrap_or(0)
-    });
-    
-    // Calculate USD value (simplified - would use price oracle)
-    let usd_value = bitcoin_balance as u128 * 45_000; // $45,000 per BTC
-    
-    BitcoinBalanceResponse {
-        principal,
-        bitcoin_balance,
-        usd_value,
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        Self {
+            source_chain: "injective".to_string(),
+        }
     }
 }
 
-#[query]
-pub fn get_total_bitcoin_converted() -> u64 {
-    TOTAL_BITCOIN_CONVERTED.with(|total| {
-        *total.borrow()
-    })
+impl_candid_storable!(BridgeConfig);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DeliveryStatus {
+    Queued,
+    InFlight,
+    Delivered { reference: String },
+    Failed,
 }
 
-#[query]
-pub fn get_yield_accumulator() -> u128 {
-    YIELD_ACCUMULATOR.with(|acc| {
-        *acc.borrow()
-    })
+// Queued message; `message` is the canonical `satsuma-messages` envelope and
+// `payload` the Injective `ExecuteIcpMessage` wrapping it
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct OutboundMessage {
+    pub nonce: u64,
+    pub destination_chain: String,
+    pub destination_address: String,
+    pub message: String,
+    pub payload: Vec<u8>,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: u64, // seconds
+    pub updated_at: u64, // seconds
 }
 
-#[query]
-pub fn get_injective_config() -> InjectiveConfig {
-    INJECTIVE_CONFIG.with(|cfg| {
-        cfg.borrow().clone()
-    })
+impl_candid_storable!(OutboundMessage);
+
+// Merkle root over the Injective depositors' shares of one bridged skim
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct YieldAttribution {
+    pub nonce: u64,
+    pub root: String, // hex
+    pub recipient: String,
+    pub amount: u128,
+    pub received_at: u64, // seconds
 }
 
-// Helper functions
-fn convert_usdc_to_bitcoin(usdc_amount: u128) -> u64 {
-    // Simplified conversion - in production would use price oracle
-    // Assuming 1 BTC = $45,000 and 6 decimal places for USDC
-    let bitcoin_price_usd = 45_000_000_000; // $45,000 in microdollars
-    let conversion = (usdc_amount * 1_000_000) / bitcoin_price_usd; // Convert to satoshis
-    conversion as u64
+impl_candid_storable!(YieldAttribution);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct OutboundMessageV2 {
+    nonce: u64,
+    destination_chain: String,
+    destination_address: String,
+    message: CrossChainMessage,
+    payload: Vec<u8>,
+    status: DeliveryStatus,
+    attempts: u32,
+    last_error: Option<String>,
+    created_at: u64,
+    updated_at: u64,
 }
 
-fn distribute_bitcoin_to_users(bitcoin_amount: u64, total_yield: u128) {
-    let user_balances = BALANCES.with(|b| {
-        let map = b.borrow();
-        map.clone()
-    });
-    
-    for (principal, balance) in user_balances {
-        if balance > 0 {
-            let user_share = (balance as u64 * bitcoin_amount) / (total_yield as u64);
-            if user_share > 0 {
-                BITCOIN_BALANCES.with(|btc| {
-                    let mut map = btc.borrow_mut();
-                    let entry = map.entry(principal).or_insert(0);
-                    *entry += user_share;
-                });
-            }
+impl_candid_storable!(OutboundMessageV2);
+
+impl From<OutboundMessageV2> for OutboundMessage {
+    fn from(old: OutboundMessageV2) -> Self {
+        // The old payload (`ExecuteFromIcp`) is still accepted by the vault, so it is kept as is
+        let message = match old.message.clone().into_icp_message() {
+            Some(message) => String::from_utf8(satsuma_messages::encode(&Envelope::new(old.nonce, message)))
+                .expect("wire messages are utf-8"),
+            None => format!("{:?}", old.message),
+        };
+        Self {
+            nonce: old.nonce,
+            destination_chain: old.destination_chain,
+            destination_address: old.destination_address,
+            message,
+            payload: old.payload,
+            status: old.status,
+            attempts: old.attempts,
+            last_error: old.last_error,
+            created_at: old.created_at,
+            updated_at: old.updated_at,
         }
     }
 }
 
-fn is_valid_cross_chain_caller(caller: &str) -> bool {
-    // In production, this would verify the caller is from Axelar or authorized bridge
-    caller.starts_with("axelar") || caller.starts_with("injective")
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TransportKind {
+    // Axelar gateway or relayer canister exposing `call_contract`
+    Relayer(Principal),
+    // Only available in builds with the `mock-transport` feature
+    Mock,
 }
 
-fn send_cross_chain_message(destination: &str, message: CrossChainMessage) {
-    // Placeholder for cross-chain message sending
-    // In production, this would use Axelar GMP or similar
-    ic_cdk::api::print(format!("Sending message to {}: {:?}", destination, message));
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransportConfig {
+    pub transport: Option<TransportKind>,
+    pub max_attempts: u32,
 }
 
-// Admin functions
-#[update]
-pub fn set_bitcoin_price(price_usd: u128) {
-    let caller_id = caller().to_text();
-    // Only allow authorized principals
-    if caller_id != "admin_principal_here" {
-        return;
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            transport: None,
+            max_attempts: DEFAULT_MAX_DELIVERY_ATTEMPTS,
+        }
     }
-    
-    // Update Bitcoin price (would be stored in state)
-    ic_cdk::api::print(format!("Bitcoin price updated to ${}", price_usd));
 }
 
-#[update]
-pub fn emergency_withdraw(principal: String) {
-    let caller_id = caller().to_text();
-    if caller_id != principal {
-        return;
+impl_candid_storable!(TransportConfig);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RelayerCallContractArgs {
+    pub destination_chain: String,
+    pub destination_address: String,
+    pub nonce: u64,
+    pub payload: Vec<u8>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BitcoinConversionRequest {
+    pub principal: String,
+    pub usdc_amount: u128,
+}
+
+// Lifecycle
+#[init]
+fn init(args: InitArgs) {
+    insert_admin(args.admin);
+    STORED_LAYOUT_VERSION.with(|v| {
+        v.borrow_mut()
+            .set(LAYOUT_VERSION)
+            .expect("failed to write layout version");
+    });
+    arm_timers();
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    migrate_stable_layout();
+    arm_timers();
+}
+
+// Helper: Bring stable memory written by an older build up to LAYOUT_VERSION
+fn migrate_stable_layout() {
+    // All state lives in stable memory; only the layout version needs checking
+    let stored = STORED_LAYOUT_VERSION.with(|v| *v.borrow().get());
+    if stored > LAYOUT_VERSION {
+        ic_cdk::trap(&format!(
+            "stable memory layout {} is newer than supported layout {}",
+            stored, LAYOUT_VERSION
+        ));
     }
-    
-    // Return all balances to user
-    let balance = BALANCES.with(|b| {
-        let mut map = b.borrow_mut();
-        map.remove(&principal).unwrap_or(0)
+    // Layout 0 means the previous build kept everything on the heap, so there is
+    // nothing to carry over
+    if stored < 2 {
+        // Layout 2 adds the reward index; every existing balance starts at index 0
+        let total_shares = BALANCES.with(|b| b.borrow().iter().map(|(_, balance)| balance).sum::<u128>());
+        REWARD_STATE.with(|s| {
+            s.borrow_mut()
+                .set(RewardState {
+                    total_shares,
+                    ..RewardState::default()
+                })
+                .expect("failed to write reward state");
+        });
+    }
+    if stored < 3 {
+        migrate_outbound_queue_v2();
+    }
+    STORED_LAYOUT_VERSION.with(|v| {
+        v.borrow_mut()
+            .set(LAYOUT_VERSION)
+            .expect("failed to write layout version");
     });
+}
+
+// Yield management functions
+// Entry point for relayed Axelar GMP payloads from the Injective vault (JSON or ABI encoded)
+#[update]
+pub fn receive_injective_message(
+    source_chain: String,
+    source_address: String,
+    payload: Vec<u8>,
+) -> Result<(), SatsumaError> {
+    let envelope = satsuma_messages::axelar::decode_gmp_payload(&payload)
+        .map_err(|err| SatsumaError::BridgeError(err.to_string()))?;
+    verify_inbound_message(&InboundEnvelope {
+        source_chain,
+        source_address,
+        nonce: envelope.nonce,
+    })?;
     
-    let bitcoin_balance = BITCOIN_BALANCES.with(|btc| {
-        let mut map = btc.borrow_mut();
-        map.remove(&principal).unwrap_or(0)
+    match envelope.message {
+        InjectiveToIcp::DepositYield { principal, amount, attribution_root } => {
+            if let Some(root) = attribution_root {
+                YIELD_ATTRIBUTIONS.with(|a| {
+                    a.borrow_mut().insert(envelope.nonce, YieldAttribution {
+                        nonce: envelope.nonce,
+                        root: attribution::to_hex(&root),
+                        recipient: principal.clone(),
+                        amount,
+                        received_at: now_seconds(),
+                    })
+                });
+            }
+            record_yield(&principal, amount)
+        },
+    }
+}
+
+#[update]
+pub fn convert_yield_to_bitcoin() -> Result<u64, SatsumaError> {
+    let total = YIELD_ACCUMULATOR.with(|acc| *acc.borrow().get());
+    let threshold = SCHEDULE_CONFIG.with(|cfg| cfg.borrow().get().conversion_threshold);
+    if total < threshold {
+        return Err(SatsumaError::BelowThreshold {
+            amount: total,
+            threshold,
+        });
+    }
+    let conversion_amount = total;
+    
+    // Convert to Bitcoin at the stored price; fails before touching state if the price is stale
+    // or the reserve does not hold enough ckBTC to back the credit
+    let bitcoin_amount = convert_usdc_to_bitcoin(conversion_amount)?;
+    let reserve = CKBTC_RESERVE.with(|r| *r.borrow().get());
+    if reserve < bitcoin_amount {
+        return Err(SatsumaError::InsufficientBalance {
+            required: bitcoin_amount as u128,
+            available: reserve as u128,
+        });
+    }
+    CKBTC_RESERVE.with(|r| {
+        r.borrow_mut()
+            .set(reserve - bitcoin_amount)
+            .expect("failed to update ckbtc reserve");
+    });
+    YIELD_ACCUMULATOR.with(|acc| {
+        acc.borrow_mut().set(0).expect("failed to reset yield accumulator");
+    });
+    
+    // Distribute Bitcoin to all users proportionally
+    distribute_bitcoin_to_users(bitcoin_amount);
+    
+    // Update total Bitcoin converted
+    add_total_bitcoin_converted(bitcoin_amount);
+    Ok(bitcoin_amount)
+}
+
+// Moves the caller's distributed BTC into their Bitcoin balance
+#[update]
+pub fn claim_bitcoin() -> Result<u64, SatsumaError> {
+    let caller_id = caller().to_text();
+    settle_rewards(&caller_id);
+    Ok(BITCOIN_BALANCES.with(|btc| btc.borrow().get(&caller_id).unwrap_or(0)))
+}
+
+// Injective management functions
+#[update]
+pub fn set_injective_config(config: InjectiveConfig) -> Result<(), SatsumaError> {
+    // Only allow authorized principals to set config
+    require_admin()?;
+    
+    INJECTIVE_CONFIG.with(|cfg| {
+        cfg.borrow_mut().set(config).expect("failed to store injective config");
+    });
+    Ok(())
+}
+
+#[update]
+pub fn execute_injective_deposit(envelope: InboundEnvelope, user: String, amount: u128) -> Result<(), SatsumaError> {
+    // Verify this is a cross-chain message from the Injective vault, relayed by a trusted bridge
+    verify_inbound_message(&envelope)?;
+    
+    // Update user's balance
+    let balance = yield_balance(&user);
+    set_yield_balance(&user, balance + amount);
+    
+    // Trigger yield skimming on Injective
+    queue_yield_skim(user).map(|_| ())
+}
+
+#[update]
+pub fn trigger_injective_yield_skim(recipient: String) -> Result<(), SatsumaError> {
+    require_admin()?;
+    queue_yield_skim(recipient).map(|_| ())
+}
+
+// Outbound messaging functions
+#[update]
+pub fn set_transport_config(config: TransportConfig) -> Result<(), SatsumaError> {
+    require_admin()?;
+    if config.max_attempts == 0 {
+        return Err(SatsumaError::InvalidArgument("max_attempts must be positive".to_string()));
+    }
+    if matches!(config.transport, Some(TransportKind::Mock)) && !cfg!(feature = "mock-transport") {
+        return Err(SatsumaError::InvalidArgument(
+            "the mock transport requires the mock-transport feature".to_string(),
+        ));
+    }
+    TRANSPORT_CONFIG.with(|cfg| {
+        cfg.borrow_mut().set(config).expect("failed to store transport config");
+    });
+    Ok(())
+}
+
+// Delivers every queued message; failed attempts stay queued until max_attempts
+#[update]
+pub async fn flush_outbound_queue() -> Result<u32, SatsumaError> {
+    require_admin()?;
+    Ok(deliver_queued_messages().await)
+}
+
+// Puts a failed (or stuck in-flight) message back in the queue and delivers it
+#[update]
+pub async fn retry_outbound_message(nonce: u64) -> Result<OutboundMessage, SatsumaError> {
+    require_admin()?;
+    let mut message = find_outbound_message(nonce)?;
+    if let DeliveryStatus::Delivered { .. } = message.status {
+        return Err(SatsumaError::InvalidArgument(format!("message {} was already delivered", nonce)));
+    }
+    message.status = DeliveryStatus::Queued;
+    message.attempts = 0;
+    OUTBOUND_QUEUE.with(|q| q.borrow_mut().insert(nonce, message));
+    deliver_outbound_message(nonce).await;
+    find_outbound_message(nonce)
+}
+
+#[cfg(feature = "mock-transport")]
+#[update]
+pub fn set_mock_transport_failing(failing: bool) -> Result<(), SatsumaError> {
+    require_admin()?;
+    MOCK_TRANSPORT_FAILING.with(|f| *f.borrow_mut() = failing);
+    Ok(())
+}
+
+// ckBTC functions
+#[update]
+pub fn set_ckbtc_ledger(ledger: Principal) -> Result<(), SatsumaError> {
+    require_admin()?;
+    CKBTC_CONFIG.with(|cfg| {
+        let mut cell = cfg.borrow_mut();
+        let mut config = cell.get().clone();
+        config.ledger = Some(ledger);
+        cell.set(config).expect("failed to store ckbtc config");
+    });
+    Ok(())
+}
+
+#[update]
+pub async fn withdraw_ckbtc(to: Account, amount: u64) -> Result<u64, SatsumaError> {
+    if amount == 0 {
+        return Err(SatsumaError::InvalidArgument("amount must be positive".to_string()));
+    }
+    let caller_id = caller().to_text();
+    let ledger = ckbtc_ledger()?;
+    let fee = icrc1_fee(ledger).await?;
+    settle_rewards(&caller_id);
+    pay_out_ckbtc(ledger, &caller_id, to, amount, fee).await
+}
+
+// Moves unallocated ckBTC already sent to the canister into the conversion reserve.
+// Only a surplus over everything already owed can be reserved.
+#[update]
+pub async fn fund_ckbtc_reserve(amount: u64) -> Result<u64, SatsumaError> {
+    require_admin()?;
+    let ledger = ckbtc_ledger()?;
+    let ledger_balance = icrc1_balance_of(ledger, Account {
+        owner: ic_cdk::api::id(),
+        subaccount: None,
+    })
+    .await?;
+    
+    // Read after the await so a concurrent call cannot reserve the same surplus
+    let owed = credited_ckbtc_total();
+    let available = ledger_balance.saturating_sub(owed);
+    if amount > available {
+        return Err(SatsumaError::InsufficientBalance {
+            required: amount as u128,
+            available: available as u128,
+        });
+    }
+    Ok(CKBTC_RESERVE.with(|r| {
+        let mut cell = r.borrow_mut();
+        let reserve = *cell.get() + amount;
+        cell.set(reserve).expect("failed to update ckbtc reserve");
+        reserve
+    }))
+}
+
+#[update]
+pub fn set_ckbtc_minter(minter: Principal) -> Result<(), SatsumaError> {
+    require_admin()?;
+    CKBTC_CONFIG.with(|cfg| {
+        let mut cell = cfg.borrow_mut();
+        let mut config = cell.get().clone();
+        config.minter = Some(minter);
+        cell.set(config).expect("failed to store ckbtc config");
+    });
+    Ok(())
+}
+
+#[update]
+pub async fn withdraw_btc(address: String, amount: u64) -> Result<BtcRetrieval, SatsumaError> {
+    if amount == 0 {
+        return Err(SatsumaError::InvalidArgument("amount must be positive".to_string()));
+    }
+    let caller_id = caller().to_text();
+    let ledger = ckbtc_ledger()?;
+    let minter = ckbtc_minter()?;
+    let fee = icrc1_fee(ledger).await?;
+    
+    // 1. Debit up front (amount plus the approve fee) so the balance cannot be spent twice
+    settle_rewards(&caller_id);
+    let debit = amount.checked_add(fee).ok_or(SatsumaError::InvalidArgument("amount too large".to_string()))?;
+    debit_bitcoin_balance(&caller_id, debit)?;
+    
+    // 2. Let the minter burn `amount` of the canister's ckBTC
+    let approve = ApproveArgs {
+        from_subaccount: None,
+        spender: Account {
+            owner: minter,
+            subaccount: None,
+        },
+        amount: Nat::from(amount),
+        expected_allowance: None,
+        expires_at: None,
+        fee: Some(Nat::from(fee)),
+        memo: None,
+        created_at_time: None,
+    };
+    if let Err(err) = icrc2_approve(ledger, approve).await {
+        credit_bitcoin_balance(&caller_id, debit);
+        return Err(err);
+    }
+    
+    // 3. Ask the minter to send native BTC; the approve fee is spent either way
+    let block_index = match retrieve_btc_with_approval(minter, address.clone(), amount).await {
+        Ok(block_index) => block_index,
+        Err(err) => {
+            credit_bitcoin_balance(&caller_id, amount);
+            return Err(err);
+        },
+    };
+    
+    let now = now_seconds();
+    let retrieval = BtcRetrieval {
+        block_index,
+        principal: caller_id,
+        address,
+        amount,
+        status: RetrieveBtcStatusV2::Pending,
+        refunded: false,
+        created_at: now,
+        updated_at: now,
+    };
+    BTC_RETRIEVALS.with(|r| r.borrow_mut().insert(block_index, retrieval.clone()));
+    Ok(retrieval)
+}
+
+// Polls the minter and refunds the user once the minter has reimbursed the canister
+#[update]
+pub async fn update_retrieve_btc_status(block_index: u64) -> Result<BtcRetrieval, SatsumaError> {
+    let retrieval = find_btc_retrieval(block_index)?;
+    if retrieval.refunded || matches!(retrieval.status, RetrieveBtcStatusV2::Confirmed { .. }) {
+        return Ok(retrieval);
+    }
+    let minter = ckbtc_minter()?;
+    let (status,): (RetrieveBtcStatusV2,) = ic_cdk::call(
+        minter,
+        "retrieve_btc_status_v2",
+        (RetrieveBtcStatusRequest { block_index },),
+    )
+    .await
+    .map_err(ledger_call_error)?;
+    
+    // Re-read after the await so a concurrent update cannot refund twice
+    let mut retrieval = find_btc_retrieval(block_index)?;
+    if let RetrieveBtcStatusV2::Reimbursed(ReimbursedAmount { amount }) = &status {
+        if !retrieval.refunded {
+            credit_bitcoin_balance(&retrieval.principal, *amount);
+            retrieval.refunded = true;
+        }
+    }
+    retrieval.status = status;
+    retrieval.updated_at = now_seconds();
+    BTC_RETRIEVALS.with(|r| r.borrow_mut().insert(block_index, retrieval.clone()));
+    Ok(retrieval)
+}
+
+#[update]
+pub async fn reconcile_ckbtc() -> Result<CkbtcReconciliation, SatsumaError> {
+    require_admin()?;
+    let ledger = ckbtc_ledger()?;
+    let ledger_balance = icrc1_balance_of(ledger, Account {
+        owner: ic_cdk::api::id(),
+        subaccount: None,
+    })
+    .await?;
+    let credited_total = credited_ckbtc_total();
+    
+    let report = CkbtcReconciliation {
+        ledger_balance,
+        credited_total,
+        surplus: ledger_balance.saturating_sub(credited_total),
+        deficit: credited_total.saturating_sub(ledger_balance),
+        checked_at: now_seconds(),
+    };
+    CKBTC_RECONCILIATION.with(|r| {
+        r.borrow_mut()
+            .set(StoredReconciliation { last: Some(report.clone()) })
+            .expect("failed to store ckbtc reconciliation");
+    });
+    Ok(report)
+}
+
+// Native BTC deposit functions
+#[update]
+pub fn set_native_btc_config(config: NativeBtcConfig) -> Result<(), SatsumaError> {
+    require_admin()?;
+    if config.min_confirmations == 0 {
+        return Err(SatsumaError::InvalidArgument("min_confirmations must be positive".to_string()));
+    }
+    NATIVE_BTC_CONFIG.with(|cfg| {
+        cfg.borrow_mut().set(config).expect("failed to store native btc config");
+    });
+    Ok(())
+}
+
+#[update]
+pub async fn get_btc_deposit_address() -> Result<String, SatsumaError> {
+    btc_deposit_address(caller()).await
+}
+
+// Credits the caller's confirmed, not yet credited UTXOs to their native Bitcoin balance
+#[update]
+pub async fn sync_btc_deposits() -> Result<BtcDepositSync, SatsumaError> {
+    let principal = caller();
+    let address = btc_deposit_address(principal).await?;
+    let config = NATIVE_BTC_CONFIG.with(|cfg| cfg.borrow().get().clone());
+    let (utxos, tip_height) = confirmed_utxos(&address, &config).await?;
+    
+    let mut sync = BtcDepositSync {
+        address,
+        credited_utxos: 0,
+        credited_amount: 0,
+        tip_height,
+    };
+    for utxo in utxos {
+        let outpoint = outpoint_key(&utxo.outpoint.txid, utxo.outpoint.vout);
+        let credited = CREDITED_UTXOS.with(|c| c.borrow().contains_key(&outpoint));
+        if credited || utxo.value == 0 {
+            continue;
+        }
+        CREDITED_UTXOS.with(|c| c.borrow_mut().insert(outpoint, utxo.value));
+        credit_native_btc_balance(&principal.to_text(), utxo.value);
+        sync.credited_utxos += 1;
+        sync.credited_amount += utxo.value;
+    }
+    Ok(sync)
+}
+
+// Sends `amount` of the caller's native Bitcoin balance to a segwit `destination`, spending
+// credited UTXOs at their own deposit address. The network fee is debited on top of `amount`.
+#[update]
+pub async fn withdraw_native_btc(destination: String, amount: u64) -> Result<NativeBtcWithdrawal, SatsumaError> {
+    if amount < BTC_DUST_LIMIT_SATS {
+        return Err(SatsumaError::InvalidArgument(format!(
+            "amount must be at least {} satoshis",
+            BTC_DUST_LIMIT_SATS
+        )));
+    }
+    let principal = caller();
+    let principal_id = principal.to_text();
+    let config = NATIVE_BTC_CONFIG.with(|cfg| cfg.borrow().get().clone());
+    let destination_script = script_pubkey_for_address(config.network, &destination)?;
+    let public_key = principal_public_key(&config, principal).await?;
+    let pubkey_hash = hash160(&public_key);
+    let address = p2wpkh_address(config.network, &public_key)?;
+    let fee_rate = fee_msat_per_vbyte(config.network).await?;
+    let (mut utxos, _) = confirmed_utxos(&address, &config).await?;
+    
+    // 1. Spend only UTXOs credited to the caller that no other withdrawal is spending
+    utxos.retain(|utxo| {
+        let outpoint = outpoint_key(&utxo.outpoint.txid, utxo.outpoint.vout);
+        CREDITED_UTXOS.with(|c| c.borrow().contains_key(&outpoint))
+            && !SPENT_UTXOS.with(|s| s.borrow().contains_key(&outpoint))
+    });
+    let (inputs, mut fee) = select_native_utxos(utxos, amount, fee_rate)?;
+    let total: u64 = inputs.iter().map(|input| input.value).sum();
+    let mut change = total - amount - fee;
+    let mut outputs = vec![TxOutput {
+        value: amount,
+        script_pubkey: destination_script,
+    }];
+    if change < BTC_DUST_LIMIT_SATS {
+        fee += change;
+        change = 0;
+    } else {
+        outputs.push(TxOutput {
+            value: change,
+            script_pubkey: p2wpkh_script(&pubkey_hash),
+        });
+    }
+    let tx = UnsignedTx {
+        version: 2,
+        inputs,
+        outputs,
+        lock_time: 0,
+    };
+    
+    // 2. Debit up front (amount plus fee) so neither the balance nor the UTXOs can be spent twice
+    let debit = amount + fee;
+    reserve_native_withdrawal(&principal_id, &tx, debit, ic_cdk::api::time())?;
+    
+    // 3. Sign with the caller's derived key and broadcast; refund if either fails
+    if let Err(err) = sign_and_send(&config, principal, &public_key, &tx).await {
+        release_native_withdrawal(&principal_id, &tx, debit);
+        return Err(err);
+    }
+    let mut txid = tx.txid();
+    txid.reverse();
+    Ok(NativeBtcWithdrawal {
+        txid: hex(&txid),
+        destination,
+        amount,
+        fee,
+        change,
+    })
+}
+
+// Bridge functions
+#[update]
+pub fn add_trusted_bridge(bridge: Principal) -> Result<(), SatsumaError> {
+    require_admin()?;
+    TRUSTED_BRIDGES.with(|b| b.borrow_mut().insert(bridge, ic_cdk::api::time()));
+    Ok(())
+}
+
+#[update]
+pub fn remove_trusted_bridge(bridge: Principal) -> Result<(), SatsumaError> {
+    require_admin()?;
+    TRUSTED_BRIDGES.with(|b| b.borrow_mut().remove(&bridge));
+    Ok(())
+}
+
+#[update]
+pub fn set_bridge_config(config: BridgeConfig) -> Result<(), SatsumaError> {
+    require_admin()?;
+    if config.source_chain.is_empty() {
+        return Err(SatsumaError::InvalidArgument("source_chain must not be empty".to_string()));
+    }
+    BRIDGE_CONFIG.with(|cfg| {
+        cfg.borrow_mut().set(config).expect("failed to store bridge config");
+    });
+    Ok(())
+}
+
+// Scheduling functions
+#[update]
+pub fn set_schedule_config(config: ScheduleConfig) -> Result<(), SatsumaError> {
+    require_admin()?;
+    if config.conversion_threshold == 0 {
+        return Err(SatsumaError::InvalidArgument("conversion_threshold must be positive".to_string()));
+    }
+    if config.skim_interval_seconds > 0 && config.skim_recipient.is_empty() {
+        return Err(SatsumaError::InvalidArgument("skim_recipient is required for periodic skims".to_string()));
+    }
+    SCHEDULE_CONFIG.with(|cfg| {
+        cfg.borrow_mut().set(config).expect("failed to store schedule config");
+    });
+    arm_timers();
+    Ok(())
+}
+
+// Query functions
+#[query]
+pub fn get_balance(principal: String) -> Result<BalanceResponse, SatsumaError> {
+    let balance = BALANCES.with(|b| {
+        let map = b.borrow();
+        map.get(&principal).unwrap_or(0)
+    });
+    
+    let bitcoin_balance = bitcoin_balance_of(&principal);
+    
+    Ok(BalanceResponse { 
+        principal, 
+        balance, 
+        bitcoin_balance 
+    })
+}
+
+#[query]
+pub fn my_balance() -> Result<BalanceResponse, SatsumaError> {
+    let caller_id = caller().to_text();
+    get_balance(caller_id)
+}
+
+#[query]
+pub fn get_bitcoin_balance(principal: String) -> Result<BitcoinBalanceResponse, SatsumaError> {
+    let bitcoin_balance = bitcoin_balance_of(&principal);
+    
+    // Valued at the last stored price, stale or not; this is informational only
+    let price = stored_bitcoin_price().ok_or(SatsumaError::StalePrice {
+        updated_at: 0,
+        max_age_seconds: MAX_PRICE_AGE_SECONDS,
+    })?;
+    let usd_value = bitcoin_balance as u128 * price.price_usd_micros / SATOSHIS_PER_BTC;
+    
+    Ok(BitcoinBalanceResponse {
+        principal,
+        bitcoin_balance,
+        usd_value,
+    })
+}
+
+#[query]
+pub fn get_total_bitcoin_converted() -> Result<u64, SatsumaError> {
+    Ok(TOTAL_BITCOIN_CONVERTED.with(|total| {
+        *total.borrow().get()
+    }))
+}
+
+#[query]
+pub fn get_yield_accumulator() -> Result<u128, SatsumaError> {
+    Ok(YIELD_ACCUMULATOR.with(|acc| {
+        *acc.borrow().get()
+    }))
+}
+
+#[query]
+pub fn get_injective_config() -> Result<InjectiveConfig, SatsumaError> {
+    Ok(INJECTIVE_CONFIG.with(|cfg| {
+        cfg.borrow().get().clone()
+    }))
+}
+
+#[query]
+pub fn get_ckbtc_config() -> Result<CkbtcConfig, SatsumaError> {
+    Ok(CKBTC_CONFIG.with(|cfg| cfg.borrow().get().clone()))
+}
+
+#[query]
+pub fn get_trusted_bridges() -> Result<Vec<Principal>, SatsumaError> {
+    Ok(TRUSTED_BRIDGES.with(|b| b.borrow().iter().map(|(bridge, _)| bridge).collect()))
+}
+
+#[query]
+pub fn get_bridge_config() -> Result<BridgeConfig, SatsumaError> {
+    Ok(BRIDGE_CONFIG.with(|cfg| cfg.borrow().get().clone()))
+}
+
+#[query]
+pub fn is_message_processed(message_id: String) -> Result<bool, SatsumaError> {
+    Ok(PROCESSED_MESSAGES.with(|p| p.borrow().contains_key(&message_id)))
+}
+
+#[query]
+pub fn get_yield_attribution(nonce: u64) -> Result<YieldAttribution, SatsumaError> {
+    YIELD_ATTRIBUTIONS
+        .with(|a| a.borrow().get(&nonce))
+        .ok_or(SatsumaError::InvalidArgument(format!("no yield attribution for message {}", nonce)))
+}
+
+// Check a depositor's share of bridged yield against the root that came with it
+#[query]
+pub fn verify_yield_share(
+    nonce: u64,
+    account: String,
+    amount: u128,
+    proof: Vec<String>,
+) -> Result<bool, SatsumaError> {
+    let attribution = get_yield_attribution(nonce)?;
+    let root = attribution::from_hex(&attribution.root).map_err(|err| SatsumaError::InvalidArgument(err.to_string()))?;
+    let proof = proof
+        .iter()
+        .map(|hash| attribution::from_hex(hash))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| SatsumaError::InvalidArgument(err.to_string()))?;
+    Ok(attribution::verify_proof(&root, &YieldShare { account, amount }, &proof))
+}
+
+#[query]
+pub fn get_transport_config() -> Result<TransportConfig, SatsumaError> {
+    Ok(TRANSPORT_CONFIG.with(|cfg| cfg.borrow().get().clone()))
+}
+
+#[query]
+pub fn get_outbound_message(nonce: u64) -> Result<OutboundMessage, SatsumaError> {
+    find_outbound_message(nonce)
+}
+
+#[query]
+pub fn get_outbound_messages(status: Option<DeliveryStatus>) -> Result<Vec<OutboundMessage>, SatsumaError> {
+    Ok(OUTBOUND_QUEUE.with(|q| {
+        q.borrow()
+            .iter()
+            .map(|(_, message)| message)
+            .filter(|message| status.as_ref().map_or(true, |status| &message.status == status))
+            .collect()
+    }))
+}
+
+#[cfg(feature = "mock-transport")]
+#[query]
+pub fn get_mock_deliveries() -> Result<Vec<OutboundMessage>, SatsumaError> {
+    Ok(MOCK_DELIVERIES.with(|d| d.borrow().clone()))
+}
+
+#[query]
+pub fn get_schedule() -> Result<ScheduleResponse, SatsumaError> {
+    Ok(ScheduleResponse {
+        config: SCHEDULE_CONFIG.with(|cfg| cfg.borrow().get().clone()),
+        status: SCHEDULE_STATUS.with(|s| s.borrow().get().clone()),
+    })
+}
+
+#[query]
+pub fn get_reward_state() -> Result<RewardState, SatsumaError> {
+    Ok(REWARD_STATE.with(|s| s.borrow().get().clone()))
+}
+
+#[query]
+pub fn get_native_btc_config() -> Result<NativeBtcConfig, SatsumaError> {
+    Ok(NATIVE_BTC_CONFIG.with(|cfg| cfg.borrow().get().clone()))
+}
+
+#[query]
+pub fn get_native_btc_held() -> Result<u64, SatsumaError> {
+    Ok(NATIVE_BTC_HELD.with(|n| *n.borrow().get()))
+}
+
+#[query]
+pub fn get_native_btc_balance(principal: String) -> Result<u64, SatsumaError> {
+    Ok(NATIVE_BTC_BALANCES.with(|native| native.borrow().get(&principal).unwrap_or(0)))
+}
+
+#[query]
+pub fn retrieve_btc_status(block_index: u64) -> Result<BtcRetrieval, SatsumaError> {
+    find_btc_retrieval(block_index)
+}
+
+#[query]
+pub fn get_btc_retrievals(principal: String) -> Result<Vec<BtcRetrieval>, SatsumaError> {
+    Ok(BTC_RETRIEVALS.with(|r| {
+        r.borrow()
+            .iter()
+            .map(|(_, retrieval)| retrieval)
+            .filter(|retrieval| retrieval.principal == principal)
+            .collect()
+    }))
+}
+
+#[query]
+pub fn get_ckbtc_reserve() -> Result<u64, SatsumaError> {
+    Ok(CKBTC_RESERVE.with(|r| *r.borrow().get()))
+}
+
+#[query]
+pub fn get_last_ckbtc_reconciliation() -> Result<Option<CkbtcReconciliation>, SatsumaError> {
+    Ok(CKBTC_RECONCILIATION.with(|r| r.borrow().get().last.clone()))
+}
+
+#[query]
+pub fn get_bitcoin_price() -> Result<BitcoinPrice, SatsumaError> {
+    stored_bitcoin_price().ok_or(SatsumaError::StalePrice {
+        updated_at: 0,
+        max_age_seconds: MAX_PRICE_AGE_SECONDS,
+    })
+}
+
+// Helper functions
+fn convert_usdc_to_bitcoin(usdc_amount: u128) -> Result<u64, SatsumaError> {
+    // USDC has 6 decimals and the price is in micro-dollars, so the result is in satoshis
+    let price = fresh_bitcoin_price()?;
+    let conversion = usdc_amount * SATOSHIS_PER_BTC / price.price_usd_micros;
+    u64::try_from(conversion)
+        .map_err(|_| SatsumaError::InvalidArgument(format!("{} USDC converts to more satoshis than fit in u64", usdc_amount)))
+}
+
+fn now_seconds() -> u64 {
+    ic_cdk::api::time() / 1_000_000_000
+}
+
+fn stored_bitcoin_price() -> Option<BitcoinPrice> {
+    BITCOIN_PRICE.with(|p| p.borrow().get().price.clone())
+}
+
+fn store_bitcoin_price(price: BitcoinPrice) {
+    BITCOIN_PRICE.with(|p| {
+        p.borrow_mut()
+            .set(StoredBitcoinPrice { price: Some(price) })
+            .expect("failed to store bitcoin price");
+    });
+}
+
+// Helper: latest price, rejected if missing or older than MAX_PRICE_AGE_SECONDS
+fn fresh_bitcoin_price() -> Result<BitcoinPrice, SatsumaError> {
+    let price = stored_bitcoin_price().ok_or(SatsumaError::StalePrice {
+        updated_at: 0,
+        max_age_seconds: MAX_PRICE_AGE_SECONDS,
+    })?;
+    if now_seconds().saturating_sub(price.updated_at) > MAX_PRICE_AGE_SECONDS {
+        return Err(SatsumaError::StalePrice {
+            updated_at: price.updated_at,
+            max_age_seconds: MAX_PRICE_AGE_SECONDS,
+        });
+    }
+    Ok(price)
+}
+
+// Helper: `bitcoin_price_oracle` holds the principal of the XRC (or a mock)
+fn price_oracle_canister() -> Result<Principal, SatsumaError> {
+    let oracle = INJECTIVE_CONFIG.with(|cfg| cfg.borrow().get().bitcoin_price_oracle.clone());
+    if oracle.is_empty() {
+        return Err(SatsumaError::InvalidArgument("bitcoin_price_oracle is not set".to_string()));
+    }
+    Principal::from_text(&oracle)
+        .map_err(|_| SatsumaError::InvalidArgument(format!("invalid bitcoin_price_oracle: {}", oracle)))
+}
+
+async fn fetch_xrc_bitcoin_price(oracle: Principal) -> Result<BitcoinPrice, SatsumaError> {
+    let request = GetExchangeRateRequest {
+        base_asset: Asset {
+            symbol: "BTC".to_string(),
+            class: AssetClass::Cryptocurrency,
+        },
+        quote_asset: Asset {
+            symbol: "USD".to_string(),
+            class: AssetClass::FiatCurrency,
+        },
+        timestamp: None,
+    };
+    let (result,): (GetExchangeRateResult,) = ic_cdk::api::call::call_with_payment128(
+        oracle,
+        "get_exchange_rate",
+        (request,),
+        XRC_CALL_CYCLES,
+    )
+    .await
+    .map_err(|(code, msg)| SatsumaError::OracleError(format!("{:?}: {}", code, msg)))?;
+    
+    let rate = match result {
+        GetExchangeRateResult::Ok(rate) => rate,
+        GetExchangeRateResult::Err(err) => return Err(SatsumaError::OracleError(format!("{:?}", err))),
+    };
+    // XRC rates are fixed-point with `metadata.decimals` decimals
+    let price_usd_micros = rate.rate as u128 * MICROS_PER_USD / 10u128.pow(rate.metadata.decimals);
+    if price_usd_micros == 0 {
+        return Err(SatsumaError::OracleError("oracle returned a zero price".to_string()));
+    }
+    Ok(BitcoinPrice {
+        price_usd_micros,
+        updated_at: rate.timestamp,
+        source: PriceSource::ExchangeRateCanister,
+    })
+}
+
+async fn refresh_price_from_oracle() -> Result<BitcoinPrice, SatsumaError> {
+    let oracle = price_oracle_canister()?;
+    let price = fetch_xrc_bitcoin_price(oracle).await?;
+    store_bitcoin_price(price.clone());
+    Ok(price)
+}
+
+// Helper: O(1) pro rata distribution by bumping the reward index; the
+// remainder is carried as dust into the next distribution
+fn distribute_bitcoin_to_users(bitcoin_amount: u64) {
+    REWARD_STATE.with(|s| {
+        let mut cell = s.borrow_mut();
+        let mut state = cell.get().clone();
+        let amount = bitcoin_amount as u128 * REWARD_INDEX_SCALE + state.dust;
+        if state.total_shares == 0 {
+            state.dust = amount;
+        } else {
+            let increment = amount / state.total_shares;
+            state.btc_per_share += increment;
+            state.dust = amount - increment * state.total_shares;
+        }
+        cell.set(state).expect("failed to update reward state");
+    });
+}
+
+fn yield_balance(principal: &str) -> u128 {
+    BALANCES.with(|b| b.borrow().get(&principal.to_string()).unwrap_or(0))
+}
+
+// Helper: every change to `BALANCES` goes through here so rewards are settled
+// at the old balance and the share total stays in sync
+fn set_yield_balance(principal: &str, balance: u128) {
+    settle_rewards(principal);
+    let previous = yield_balance(principal);
+    BALANCES.with(|b| b.borrow_mut().insert(principal.to_string(), balance));
+    REWARD_STATE.with(|s| {
+        let mut cell = s.borrow_mut();
+        let mut state = cell.get().clone();
+        state.total_shares = state.total_shares - previous + balance;
+        cell.set(state).expect("failed to update reward state");
+    });
+}
+
+// Helper: (whole satoshis, scaled remainder) owed to `principal` since its checkpoint
+fn pending_rewards(principal: &str) -> (u64, u128) {
+    let index = REWARD_STATE.with(|s| s.borrow().get().btc_per_share);
+    let checkpoint = REWARD_CHECKPOINTS
+        .with(|c| c.borrow().get(&principal.to_string()))
+        .unwrap_or_default();
+    let owed = yield_balance(principal) * (index - checkpoint.btc_per_share) + checkpoint.remainder;
+    ((owed / REWARD_INDEX_SCALE) as u64, owed % REWARD_INDEX_SCALE)
+}
+
+fn settle_rewards(principal: &str) {
+    let (amount, remainder) = pending_rewards(principal);
+    if amount > 0 {
+        credit_bitcoin_balance(principal, amount);
+    }
+    let index = REWARD_STATE.with(|s| s.borrow().get().btc_per_share);
+    REWARD_CHECKPOINTS.with(|c| {
+        c.borrow_mut().insert(
+            principal.to_string(),
+            RewardCheckpoint {
+                btc_per_share: index,
+                remainder,
+            },
+        )
+    });
+}
+
+// Settled balance plus rewards not yet claimed
+fn bitcoin_balance_of(principal: &str) -> u64 {
+    let settled = BITCOIN_BALANCES.with(|btc| btc.borrow().get(&principal.to_string()).unwrap_or(0));
+    settled + pending_rewards(principal).0
+}
+
+fn credit_bitcoin_balance(principal: &str, amount: u64) {
+    BITCOIN_BALANCES.with(|btc| {
+        let mut map = btc.borrow_mut();
+        let balance = map.get(&principal.to_string()).unwrap_or(0);
+        map.insert(principal.to_string(), balance + amount);
+    });
+}
+
+fn debit_bitcoin_balance(principal: &str, amount: u64) -> Result<(), SatsumaError> {
+    BITCOIN_BALANCES.with(|btc| {
+        let mut map = btc.borrow_mut();
+        let balance = map.get(&principal.to_string()).unwrap_or(0);
+        if balance < amount {
+            return Err(SatsumaError::InsufficientBalance {
+                required: amount as u128,
+                available: balance as u128,
+            });
+        }
+        map.insert(principal.to_string(), balance - amount);
+        Ok(())
+    })
+}
+
+fn ckbtc_ledger() -> Result<Principal, SatsumaError> {
+    CKBTC_CONFIG
+        .with(|cfg| cfg.borrow().get().ledger)
+        .ok_or(SatsumaError::InvalidArgument("ckBTC ledger is not set".to_string()))
+}
+
+// Helper: satoshis the ledger must hold, i.e. settled and unsettled balances plus the reserve
+fn credited_ckbtc_total() -> u64 {
+    let reserve = CKBTC_RESERVE.with(|r| *r.borrow().get());
+    let settled = BITCOIN_BALANCES.with(|btc| btc.borrow().iter().map(|(_, balance)| balance).sum::<u64>());
+    let unsettled = BALANCES.with(|b| {
+        b.borrow()
+            .iter()
+            .map(|(principal, _)| pending_rewards(&principal).0)
+            .sum::<u64>()
+    });
+    settled + unsettled + reserve
+}
+
+// Helper: sends `amount` of ckBTC from the canister to `to`, charging `principal` the amount
+// plus the ledger fee. The debit happens before the transfer so a concurrent call cannot
+// spend the same balance while this one is awaiting; it is restored if the transfer fails.
+async fn pay_out_ckbtc(ledger: Principal, principal: &str, to: Account, amount: u64, fee: u64) -> Result<u64, SatsumaError> {
+    let debit = amount.checked_add(fee).ok_or(SatsumaError::InvalidArgument("amount too large".to_string()))?;
+    debit_bitcoin_balance(principal, debit)?;
+    
+    let transfer = TransferArg {
+        from_subaccount: None,
+        to,
+        amount: Nat::from(amount),
+        fee: Some(Nat::from(fee)),
+        memo: None,
+        created_at_time: None,
+    };
+    match icrc1_transfer(ledger, transfer).await {
+        Ok(block_index) => Ok(block_index),
+        Err(err) => {
+            // Nothing left the canister; give the user their balance back
+            credit_bitcoin_balance(principal, debit);
+            Err(err)
+        },
+    }
+}
+
+fn ckbtc_minter() -> Result<Principal, SatsumaError> {
+    CKBTC_CONFIG
+        .with(|cfg| cfg.borrow().get().minter)
+        .ok_or(SatsumaError::InvalidArgument("ckBTC minter is not set".to_string()))
+}
+
+fn find_btc_retrieval(block_index: u64) -> Result<BtcRetrieval, SatsumaError> {
+    BTC_RETRIEVALS
+        .with(|r| r.borrow().get(&block_index))
+        .ok_or(SatsumaError::InvalidArgument(format!("unknown retrieval {}", block_index)))
+}
+
+fn nat_to_u64(value: Nat) -> Result<u64, SatsumaError> {
+    u64::try_from(value.0).map_err(|_| SatsumaError::LedgerError("ledger amount exceeds u64".to_string()))
+}
+
+fn ledger_call_error((code, msg): (ic_cdk::api::call::RejectionCode, String)) -> SatsumaError {
+    SatsumaError::LedgerError(format!("{:?}: {}", code, msg))
+}
+
+async fn icrc1_fee(ledger: Principal) -> Result<u64, SatsumaError> {
+    let (fee,): (Nat,) = ic_cdk::call(ledger, "icrc1_fee", ()).await.map_err(ledger_call_error)?;
+    nat_to_u64(fee)
+}
+
+async fn icrc1_balance_of(ledger: Principal, account: Account) -> Result<u64, SatsumaError> {
+    let (balance,): (Nat,) = ic_cdk::call(ledger, "icrc1_balance_of", (account,))
+        .await
+        .map_err(ledger_call_error)?;
+    nat_to_u64(balance)
+}
+
+async fn icrc1_transfer(ledger: Principal, transfer: TransferArg) -> Result<u64, SatsumaError> {
+    let (result,): (Result<Nat, TransferError>,) = ic_cdk::call(ledger, "icrc1_transfer", (transfer,))
+        .await
+        .map_err(ledger_call_error)?;
+    let block_index = result.map_err(|err| SatsumaError::LedgerError(format!("{:?}", err)))?;
+    nat_to_u64(block_index)
+}
+
+async fn icrc2_approve(ledger: Principal, approve: ApproveArgs) -> Result<u64, SatsumaError> {
+    let (result,): (Result<Nat, ApproveError>,) = ic_cdk::call(ledger, "icrc2_approve", (approve,))
+        .await
+        .map_err(ledger_call_error)?;
+    let block_index = result.map_err(|err| SatsumaError::LedgerError(format!("{:?}", err)))?;
+    nat_to_u64(block_index)
+}
+
+async fn retrieve_btc_with_approval(minter: Principal, address: String, amount: u64) -> Result<u64, SatsumaError> {
+    let args = RetrieveBtcWithApprovalArgs {
+        address,
+        amount,
+        from_subaccount: None,
+    };
+    let (result,): (Result<RetrieveBtcOk, RetrieveBtcWithApprovalError>,) =
+        ic_cdk::call(minter, "retrieve_btc_with_approval", (args,))
+            .await
+            .map_err(ledger_call_error)?;
+    result
+        .map(|ok| ok.block_index)
+        .map_err(|err| SatsumaError::LedgerError(format!("{:?}", err)))
+}
+
+// Helper: cached P2WPKH address for `principal`, derived on first use
+async fn btc_deposit_address(principal: Principal) -> Result<String, SatsumaError> {
+    let principal_id = principal.to_text();
+    if let Some(address) = DEPOSIT_ADDRESSES.with(|a| a.borrow().get(&principal_id)) {
+        return Ok(address);
+    }
+    let config = NATIVE_BTC_CONFIG.with(|cfg| cfg.borrow().get().clone());
+    let public_key = principal_public_key(&config, principal).await?;
+    
+    let address = p2wpkh_address(config.network, &public_key)?;
+    DEPOSIT_ADDRESSES.with(|a| a.borrow_mut().insert(principal_id, address.clone()));
+    Ok(address)
+}
+
+// Helper: compressed public key derived for `principal`; it controls the deposit address
+async fn principal_public_key(config: &NativeBtcConfig, principal: Principal) -> Result<Vec<u8>, SatsumaError> {
+    let (response,) = ecdsa_public_key(EcdsaPublicKeyArgument {
+        canister_id: None,
+        derivation_path: vec![principal.as_slice().to_vec()],
+        key_id: native_btc_key_id(config),
+    })
+    .await
+    .map_err(|(code, msg)| SatsumaError::BridgeError(format!("{:?}: {}", code, msg)))?;
+    Ok(response.public_key)
+}
+
+fn native_btc_key_id(config: &NativeBtcConfig) -> EcdsaKeyId {
+    EcdsaKeyId {
+        curve: EcdsaCurve::Secp256k1,
+        name: config.ecdsa_key_name.clone(),
+    }
+}
+
+// Helper: every UTXO at `address` with at least `min_confirmations`, and the tip height
+async fn confirmed_utxos(address: &str, config: &NativeBtcConfig) -> Result<(Vec<Utxo>, u32), SatsumaError> {
+    let mut utxos = Vec::new();
+    let mut tip_height = 0;
+    let mut filter = Some(UtxoFilter::MinConfirmations(config.min_confirmations));
+    loop {
+        let (response,) = bitcoin_get_utxos(GetUtxosRequest {
+            address: address.to_string(),
+            network: config.network,
+            filter,
+        })
+        .await
+        .map_err(|(code, msg)| SatsumaError::BridgeError(format!("{:?}: {}", code, msg)))?;
+        tip_height = tip_height.max(response.tip_height);
+        utxos.extend(response.utxos);
+        
+        match response.next_page {
+            Some(page) => filter = Some(UtxoFilter::Page(page)),
+            None => break,
+        }
+    }
+    Ok((utxos, tip_height))
+}
+
+// Helper: median fee rate of recent transactions, in millisatoshi per vbyte
+async fn fee_msat_per_vbyte(network: BitcoinNetwork) -> Result<u64, SatsumaError> {
+    let (percentiles,) = bitcoin_get_current_fee_percentiles(GetCurrentFeePercentilesRequest { network })
+        .await
+        .map_err(|(code, msg)| SatsumaError::BridgeError(format!("{:?}: {}", code, msg)))?;
+    let median = percentiles
+        .get(percentiles.len() / 2)
+        .copied()
+        .unwrap_or(DEFAULT_FEE_MSAT_PER_VBYTE);
+    Ok(median.max(MIN_FEE_MSAT_PER_VBYTE))
+}
+
+// Helper: sign every input of `tx` with `principal`'s derived key and broadcast it
+async fn sign_and_send(
+    config: &NativeBtcConfig,
+    principal: Principal,
+    public_key: &[u8],
+    tx: &UnsignedTx,
+) -> Result<(), SatsumaError> {
+    let pubkey_hash = hash160(public_key);
+    let mut witnesses = Vec::new();
+    for index in 0..tx.inputs.len() {
+        let (response,) = sign_with_ecdsa(SignWithEcdsaArgument {
+            message_hash: tx.p2wpkh_sighash(index, &pubkey_hash),
+            derivation_path: vec![principal.as_slice().to_vec()],
+            key_id: native_btc_key_id(config),
+        })
+        .await
+        .map_err(|(code, msg)| SatsumaError::BridgeError(format!("{:?}: {}", code, msg)))?;
+        let mut signature = der_signature(&response.signature)?;
+        signature.push(SIGHASH_ALL as u8);
+        witnesses.push(vec![signature, public_key.to_vec()]);
+    }
+    bitcoin_send_transaction(SendTransactionRequest {
+        transaction: tx.serialize(Some(&witnesses)),
+        network: config.network,
+    })
+    .await
+    .map_err(|(code, msg)| SatsumaError::BridgeError(format!("{:?}: {}", code, msg)))
+}
+
+// Helper: largest-first selection covering `amount` plus the fee at `fee_rate` (msat/vB)
+// for a transaction with a change output. Returns the inputs and that fee.
+fn select_native_utxos(mut utxos: Vec<Utxo>, amount: u64, fee_rate: u64) -> Result<(Vec<TxInput>, u64), SatsumaError> {
+    utxos.sort_by(|a, b| b.value.cmp(&a.value));
+    let mut inputs = Vec::new();
+    let mut total = 0u64;
+    let mut fee = 0;
+    for utxo in utxos {
+        total += utxo.value;
+        inputs.push(TxInput {
+            txid: utxo.outpoint.txid,
+            vout: utxo.outpoint.vout,
+            value: utxo.value,
+            sequence: 0xffff_ffff,
+        });
+        fee = (estimate_vsize(inputs.len(), 2) * fee_rate).div_ceil(1_000);
+        if total >= amount.saturating_add(fee) {
+            return Ok((inputs, fee));
+        }
+    }
+    Err(SatsumaError::InsufficientBalance {
+        required: amount as u128 + fee as u128,
+        available: total as u128,
+    })
+}
+
+// Helper: book a native withdrawal before its first await, so concurrent calls cannot
+// spend the same balance or UTXOs. The change output (index 1) pays the caller's deposit
+// address, so it is marked credited here and never credited again by a sync.
+fn reserve_native_withdrawal(principal: &str, tx: &UnsignedTx, debit: u64, spent_at: u64) -> Result<(), SatsumaError> {
+    debit_native_btc_balance(principal, debit)?;
+    SPENT_UTXOS.with(|s| {
+        let mut spent = s.borrow_mut();
+        for input in &tx.inputs {
+            spent.insert(outpoint_key(&input.txid, input.vout), spent_at);
+        }
+    });
+    if let Some(change) = tx.outputs.get(1) {
+        CREDITED_UTXOS.with(|c| c.borrow_mut().insert(outpoint_key(&tx.txid(), 1), change.value));
+    }
+    Ok(())
+}
+
+// Helper: undo `reserve_native_withdrawal` after signing or broadcasting failed
+fn release_native_withdrawal(principal: &str, tx: &UnsignedTx, debit: u64) {
+    SPENT_UTXOS.with(|s| {
+        let mut spent = s.borrow_mut();
+        for input in &tx.inputs {
+            spent.remove(&outpoint_key(&input.txid, input.vout));
+        }
+    });
+    if tx.outputs.len() > 1 {
+        CREDITED_UTXOS.with(|c| c.borrow_mut().remove(&outpoint_key(&tx.txid(), 1)));
+    }
+    credit_native_btc_balance(principal, debit);
+}
+
+fn credit_native_btc_balance(principal: &str, amount: u64) {
+    NATIVE_BTC_BALANCES.with(|native| {
+        let mut map = native.borrow_mut();
+        let balance = map.get(&principal.to_string()).unwrap_or(0);
+        map.insert(principal.to_string(), balance + amount);
+    });
+    NATIVE_BTC_HELD.with(|n| {
+        let mut cell = n.borrow_mut();
+        let held = *cell.get();
+        cell.set(held + amount).expect("failed to update native btc held");
+    });
+}
+
+fn debit_native_btc_balance(principal: &str, amount: u64) -> Result<(), SatsumaError> {
+    NATIVE_BTC_BALANCES.with(|native| {
+        let mut map = native.borrow_mut();
+        let balance = map.get(&principal.to_string()).unwrap_or(0);
+        if balance < amount {
+            return Err(SatsumaError::InsufficientBalance {
+                required: amount as u128,
+                available: balance as u128,
+            });
+        }
+        map.insert(principal.to_string(), balance - amount);
+        Ok(())
+    })?;
+    NATIVE_BTC_HELD.with(|n| {
+        let mut cell = n.borrow_mut();
+        let held = *cell.get();
+        cell.set(held - amount).expect("failed to update native btc held");
+    });
+    Ok(())
+}
+
+// Helper: bech32 witness v0 address over HASH160 of the compressed public key
+fn p2wpkh_address(network: BitcoinNetwork, public_key: &[u8]) -> Result<String, SatsumaError> {
+    let pubkey_hash = hash160(public_key);
+    let mut data = vec![u5::try_from_u8(0).expect("0 is a valid u5")];
+    data.extend(pubkey_hash.to_base32());
+    bech32::encode(network_hrp(network), data, Variant::Bech32)
+        .map_err(|err| SatsumaError::BridgeError(format!("failed to encode address: {}", err)))
+}
+
+fn write_varint(buf: &mut Vec<u8>, n: usize) {
+    match n {
+        0..=0xfc => buf.push(n as u8),
+        0xfd..=0xffff => {
+            buf.push(0xfd);
+            buf.extend((n as u16).to_le_bytes());
+        },
+        _ => {
+            buf.push(0xfe);
+            buf.extend((n as u32).to_le_bytes());
+        },
+    }
+}
+
+fn sha256d(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(Sha256::digest(data)).to_vec()
+}
+
+fn hash160(data: &[u8]) -> Vec<u8> {
+    Ripemd160::digest(Sha256::digest(data)).to_vec()
+}
+
+fn network_hrp(network: BitcoinNetwork) -> &'static str {
+    match network {
+        BitcoinNetwork::Mainnet => "bc",
+        BitcoinNetwork::Testnet => "tb",
+        BitcoinNetwork::Regtest => "bcrt",
+    }
+}
+
+// Helper: OP_0 <pubkey_hash>
+fn p2wpkh_script(pubkey_hash: &[u8]) -> Vec<u8> {
+    let mut script = vec![0x00, 0x14];
+    script.extend(pubkey_hash);
+    script
+}
+
+// Helper: output script paying a segwit address on `network`; only P2WPKH, P2WSH
+// and P2TR destinations are accepted
+fn script_pubkey_for_address(network: BitcoinNetwork, address: &str) -> Result<Vec<u8>, SatsumaError> {
+    let invalid = |reason: String| SatsumaError::InvalidArgument(format!("invalid address {}: {}", address, reason));
+    let (hrp, data, variant) = bech32::decode(address).map_err(|err| invalid(err.to_string()))?;
+    if hrp != network_hrp(network) {
+        return Err(invalid(format!("expected a {} address", network_hrp(network))));
+    }
+    let (version, program) = data.split_first().ok_or_else(|| invalid("missing witness version".to_string()))?;
+    let version = version.to_u8();
+    let program = Vec::<u8>::from_base32(program).map_err(|err| invalid(err.to_string()))?;
+    match (version, variant, program.len()) {
+        (0, Variant::Bech32, 20) | (0, Variant::Bech32, 32) | (1, Variant::Bech32m, 32) => {},
+        _ => return Err(invalid("unsupported witness program".to_string())),
+    }
+    // OP_0 or OP_1, then the program push
+    let mut script = vec![if version == 0 { 0x00 } else { 0x50 + version }, program.len() as u8];
+    script.extend(program);
+    Ok(script)
+}
+
+// Helper: vbytes of a transaction spending `inputs` P2WPKH outputs into `outputs`
+// outputs, sized for the largest accepted script (P2WSH or P2TR)
+fn estimate_vsize(inputs: usize, outputs: usize) -> u64 {
+    11 + 68 * inputs as u64 + 43 * outputs as u64
+}
+
+// Helper: DER encoding of a 64-byte r || s signature, with s in low-S form as
+// standardness requires
+fn der_signature(signature: &[u8]) -> Result<Vec<u8>, SatsumaError> {
+    if signature.len() != 64 {
+        return Err(SatsumaError::BridgeError(format!("unexpected signature length {}", signature.len())));
+    }
+    let (r, s) = signature.split_at(32);
+    let negated = order_minus(s);
+    // Equal-length big-endian byte strings compare numerically
+    let s = if negated.as_slice() < s { negated.as_slice() } else { s };
+    let r = der_integer(r);
+    let s = der_integer(s);
+    let mut der = vec![0x30, (r.len() + s.len()) as u8];
+    der.extend(r);
+    der.extend(s);
+    Ok(der)
+}
+
+// Helper: SECP256K1_ORDER - value, for a 32-byte big-endian `value` below the order
+fn order_minus(value: &[u8]) -> Vec<u8> {
+    let mut out = vec![0u8; 32];
+    let mut borrow = 0i16;
+    for i in (0..32).rev() {
+        let mut digit = SECP256K1_ORDER[i] as i16 - value[i] as i16 - borrow;
+        borrow = if digit < 0 { 1 } else { 0 };
+        if digit < 0 {
+            digit += 256;
+        }
+        out[i] = digit as u8;
+    }
+    out
+}
+
+fn der_integer(bytes: &[u8]) -> Vec<u8> {
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len() - 1);
+    let mut value = bytes[start..].to_vec();
+    // A set high bit would read as negative
+    if value[0] & 0x80 != 0 {
+        value.insert(0, 0x00);
+    }
+    let mut out = vec![0x02, value.len() as u8];
+    out.extend(value);
+    out
+}
+
+// Helper: "txid:vout" key of an outpoint in `CREDITED_UTXOS` and `SPENT_UTXOS`
+fn outpoint_key(txid: &[u8], vout: u32) -> String {
+    format!("{}:{}", hex(txid), vout)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Helper: cancel existing timers and start one per enabled job
+fn arm_timers() {
+    TIMERS.with(|t| {
+        for timer in t.borrow_mut().drain(..) {
+            ic_cdk_timers::clear_timer(timer);
+        }
+    });
+    let config = SCHEDULE_CONFIG.with(|cfg| cfg.borrow().get().clone());
+    let now = now_seconds();
+    let mut timers = Vec::new();
+    
+    let next_conversion_at = if config.conversion_interval_seconds > 0 {
+        let interval = Duration::from_secs(config.conversion_interval_seconds);
+        timers.push(ic_cdk_timers::set_timer_interval(interval, run_scheduled_conversion));
+        Some(now + config.conversion_interval_seconds)
+    } else {
+        None
+    };
+    let next_skim_at = if config.skim_interval_seconds > 0 {
+        let interval = Duration::from_secs(config.skim_interval_seconds);
+        timers.push(ic_cdk_timers::set_timer_interval(interval, run_scheduled_skim));
+        Some(now + config.skim_interval_seconds)
+    } else {
+        None
+    };
+    
+    TIMERS.with(|t| *t.borrow_mut() = timers);
+    update_schedule_status(|status| {
+        status.next_conversion_at = next_conversion_at;
+        status.next_skim_at = next_skim_at;
+    });
+}
+
+fn run_scheduled_conversion() {
+    // Piggyback retries of undelivered outbound messages on the conversion tick
+    ic_cdk::spawn(async {
+        deliver_queued_messages().await;
+    });
+    ic_cdk::spawn(async {
+        // The cached price is usually older than MAX_PRICE_AGE_SECONDS between runs, so fetch
+        // a new one when a conversion is due; a failed refresh falls back to the cached price
+        let total = YIELD_ACCUMULATOR.with(|acc| *acc.borrow().get());
+        let threshold = SCHEDULE_CONFIG.with(|cfg| cfg.borrow().get().conversion_threshold);
+        let price_refresh_error = if total < threshold {
+            None
+        } else {
+            refresh_price_from_oracle().await.err()
+        };
+        
+        let result = convert_yield_to_bitcoin();
+        let interval = SCHEDULE_CONFIG.with(|cfg| cfg.borrow().get().conversion_interval_seconds);
+        let now = now_seconds();
+        update_schedule_status(|status| {
+            status.last_conversion = Some(ConversionRun {
+                ran_at: now,
+                result,
+                price_refresh_error,
+            });
+            status.next_conversion_at = Some(now + interval);
+        });
+    });
+}
+
+fn run_scheduled_skim() {
+    let config = SCHEDULE_CONFIG.with(|cfg| cfg.borrow().get().clone());
+    let result = queue_yield_skim(config.skim_recipient).map(|_| ());
+    let now = now_seconds();
+    update_schedule_status(|status| {
+        status.last_skim = Some(SkimRun { ran_at: now, result });
+        status.next_skim_at = Some(now + config.skim_interval_seconds);
+    });
+}
+
+fn update_schedule_status(update: impl FnOnce(&mut ScheduleStatus)) {
+    SCHEDULE_STATUS.with(|s| {
+        let mut cell = s.borrow_mut();
+        let mut status = cell.get().clone();
+        update(&mut status);
+        cell.set(status).expect("failed to store schedule status");
+    });
+}
+
+fn add_total_bitcoin_converted(bitcoin_amount: u64) {
+    TOTAL_BITCOIN_CONVERTED.with(|total_btc| {
+        let mut cell = total_btc.borrow_mut();
+        let btc = *cell.get();
+        cell.set(btc + bitcoin_amount).expect("failed to update total bitcoin converted");
+    });
+}
+
+// Admins are stored in state; canister controllers are accepted as a fallback
+// so a canister that lost its admins can still be recovered
+fn is_admin(principal: &Principal) -> bool {
+    ADMINS.with(|a| a.borrow().contains_key(principal)) || ic_cdk::api::is_controller(principal)
+}
+
+fn require_admin() -> Result<(), SatsumaError> {
+    if !is_admin(&caller()) {
+        return Err(SatsumaError::Unauthorized);
+    }
+    Ok(())
+}
+
+fn insert_admin(principal: Principal) {
+    ADMINS.with(|a| {
+        a.borrow_mut().insert(principal, ic_cdk::api::time());
+    });
+}
+
+fn admin_count() -> u64 {
+    ADMINS.with(|a| a.borrow().len())
+}
+
+fn set_pending_admin_handover(handover: Option<AdminHandover>) {
+    PENDING_ADMIN_HANDOVER.with(|p| {
+        p.borrow_mut()
+            .set(PendingAdminHandover { handover })
+            .expect("failed to store pending admin handover");
+    });
+}
+
+// Helper: accept a message only from a trusted bridge, for the configured
+// source chain and Injective vault, and only once. The id is recorded before
+// the message takes effect; a trap later in the call rolls it back.
+fn verify_inbound_message(envelope: &InboundEnvelope) -> Result<(), SatsumaError> {
+    let bridge = caller();
+    if !TRUSTED_BRIDGES.with(|b| b.borrow().contains_key(&bridge)) {
+        return Err(SatsumaError::Unauthorized);
+    }
+    
+    let source_chain = BRIDGE_CONFIG.with(|cfg| cfg.borrow().get().source_chain.clone());
+    if envelope.source_chain != source_chain {
+        return Err(SatsumaError::BridgeError(format!("unexpected source chain {}", envelope.source_chain)));
+    }
+    let vault = INJECTIVE_CONFIG.with(|cfg| cfg.borrow().get().contract_address.clone());
+    if vault.is_empty() || envelope.source_address != vault {
+        return Err(SatsumaError::BridgeError(format!(
+            "unexpected source address {}",
+            envelope.source_address
+        )));
+    }
+    
+    let message_id = envelope.message_id();
+    if PROCESSED_MESSAGES.with(|p| p.borrow().contains_key(&message_id)) {
+        return Err(SatsumaError::DuplicateMessage(message_id));
+    }
+    PROCESSED_MESSAGES.with(|p| p.borrow_mut().insert(message_id, ic_cdk::api::time()));
+    Ok(())
+}
+
+// Helper: credit `principal` with bridged yield and convert once the threshold is met
+fn record_yield(principal: &str, amount: u128) -> Result<(), SatsumaError> {
+    let balance = yield_balance(principal);
+    set_yield_balance(principal, balance + amount);
+    
+    // Accumulate yield for Bitcoin conversion
+    YIELD_ACCUMULATOR.with(|acc| {
+        let mut acc = acc.borrow_mut();
+        let total = *acc.get();
+        acc.set(total + amount).expect("failed to update yield accumulator");
+    });
+    
+    // Auto-convert to Bitcoin if threshold met. The yield is already recorded, so a
+    // failed conversion (e.g. a stale price) must not fail the delivery; the
+    // accumulator stays pending for the scheduled run
+    let _ = convert_yield_to_bitcoin();
+    Ok(())
+}
+
+fn queue_yield_skim(recipient: String) -> Result<u64, SatsumaError> {
+    let message = IcpToInjective::SkimYield {
+        recipient,
+        max_slippage_bps: None,
+    };
+    send_cross_chain_message("injective", message)
+}
+
+// Helper: queue `message` under the next nonce and start delivery in the background.
+// Returns the nonce.
+fn send_cross_chain_message(destination: &str, message: IcpToInjective) -> Result<u64, SatsumaError> {
+    let destination_address = INJECTIVE_CONFIG.with(|cfg| cfg.borrow().get().contract_address.clone());
+    if destination_address.is_empty() {
+        return Err(SatsumaError::BridgeError("injective contract_address is not set".to_string()));
+    }
+    let nonce = OUTBOUND_NONCE.with(|n| {
+        let mut cell = n.borrow_mut();
+        let nonce = *cell.get() + 1;
+        cell.set(nonce).expect("failed to update outbound nonce");
+        nonce
+    });
+    let envelope = Envelope::new(nonce, message);
+    let payload = serde_json::to_vec(&InjectiveExecuteMsg::ExecuteIcpMessage {
+        envelope: envelope.clone(),
+    })
+    .map_err(|err| SatsumaError::BridgeError(format!("failed to encode message: {}", err)))?;
+    let message = String::from_utf8(satsuma_messages::encode(&envelope)).expect("wire messages are utf-8");
+    
+    let now = now_seconds();
+    let outbound = OutboundMessage {
+        nonce,
+        destination_chain: destination.to_string(),
+        destination_address,
+        message,
+        payload,
+        status: DeliveryStatus::Queued,
+        attempts: 0,
+        last_error: None,
+        created_at: now,
+        updated_at: now,
+    };
+    OUTBOUND_QUEUE.with(|q| q.borrow_mut().insert(nonce, outbound));
+    ic_cdk::spawn(async move {
+        deliver_outbound_message(nonce).await;
+    });
+    Ok(nonce)
+}
+
+// Helper: rewrite layout-2 queue entries, which stored the stringly `CrossChainMessage`
+fn migrate_outbound_queue_v2() {
+    let mut legacy: StableBTreeMap<u64, OutboundMessageV2, Memory> =
+        StableBTreeMap::init(memory(OUTBOUND_QUEUE_MEMORY_ID));
+    let nonces: Vec<u64> = legacy.iter().map(|(nonce, _)| nonce).collect();
+    // Remove through the legacy view so old values are never decoded as the new type
+    let messages: Vec<OutboundMessageV2> = nonces.iter().filter_map(|nonce| legacy.remove(nonce)).collect();
+    drop(legacy);
+    OUTBOUND_QUEUE.with(|q| {
+        let mut queue = q.borrow_mut();
+        for message in messages {
+            queue.insert(message.nonce, message.into());
+        }
+    });
+}
+
+fn find_outbound_message(nonce: u64) -> Result<OutboundMessage, SatsumaError> {
+    OUTBOUND_QUEUE
+        .with(|q| q.borrow().get(&nonce))
+        .ok_or(SatsumaError::InvalidArgument(format!("unknown outbound message {}", nonce)))
+}
+
+async fn deliver_queued_messages() -> u32 {
+    let queued: Vec<u64> = OUTBOUND_QUEUE.with(|q| {
+        q.borrow()
+            .iter()
+            .filter(|(_, message)| message.status == DeliveryStatus::Queued)
+            .map(|(nonce, _)| nonce)
+            .collect()
+    });
+    let mut delivered = 0;
+    for nonce in queued {
+        if deliver_outbound_message(nonce).await {
+            delivered += 1;
+        }
+    }
+    delivered
+}
+
+// Helper: one delivery attempt. The message is marked in flight across the
+// await so concurrent flushes do not send it twice.
+async fn deliver_outbound_message(nonce: u64) -> bool {
+    let mut message = match find_outbound_message(nonce) {
+        Ok(message) if message.status == DeliveryStatus::Queued => message,
+        _ => return false,
+    };
+    let config = TRANSPORT_CONFIG.with(|cfg| cfg.borrow().get().clone());
+    message.status = DeliveryStatus::InFlight;
+    message.attempts += 1;
+    OUTBOUND_QUEUE.with(|q| q.borrow_mut().insert(nonce, message.clone()));
+    
+    let result = match &config.transport {
+        Some(TransportKind::Relayer(canister)) => RelayerTransport { canister: *canister }.deliver(&message).await,
+        #[cfg(feature = "mock-transport")]
+        Some(TransportKind::Mock) => MockTransport.deliver(&message).await,
+        #[cfg(not(feature = "mock-transport"))]
+        Some(TransportKind::Mock) => Err("mock transport is not available in this build".to_string()),
+        None => Err("no transport configured".to_string()),
+    };
+    
+    let delivered = result.is_ok();
+    match result {
+        Ok(reference) => {
+            message.status = DeliveryStatus::Delivered { reference };
+            message.last_error = None;
+        },
+        Err(err) => {
+            message.status = if message.attempts >= config.max_attempts {
+                DeliveryStatus::Failed
+            } else {
+                DeliveryStatus::Queued
+            };
+            message.last_error = Some(err);
+        },
+    }
+    message.updated_at = now_seconds();
+    OUTBOUND_QUEUE.with(|q| q.borrow_mut().insert(nonce, message));
+    delivered
+}
+
+// Delivers an encoded outbound message; returns a delivery reference
+trait Transport {
+    async fn deliver(&self, message: &OutboundMessage) -> Result<String, String>;
+}
+
+// Axelar gateway or relayer canister that forwards payloads to Injective
+struct RelayerTransport {
+    canister: Principal,
+}
+
+impl Transport for RelayerTransport {
+    async fn deliver(&self, message: &OutboundMessage) -> Result<String, String> {
+        let args = RelayerCallContractArgs {
+            destination_chain: message.destination_chain.clone(),
+            destination_address: message.destination_address.clone(),
+            nonce: message.nonce,
+            payload: message.payload.clone(),
+        };
+        let (result,): (Result<String, String>,) = ic_cdk::call(self.canister, "call_contract", (args,))
+            .await
+            .map_err(|(code, msg)| format!("{:?}: {}", code, msg))?;
+        result
+    }
+}
+
+// Records deliveries in memory; `set_mock_transport_failing` simulates outages
+#[cfg(feature = "mock-transport")]
+struct MockTransport;
+
+#[cfg(feature = "mock-transport")]
+impl Transport for MockTransport {
+    async fn deliver(&self, message: &OutboundMessage) -> Result<String, String> {
+        if MOCK_TRANSPORT_FAILING.with(|f| *f.borrow()) {
+            return Err("mock transport failure".to_string());
+        }
+        MOCK_DELIVERIES.with(|d| d.borrow_mut().push(message.clone()));
+        Ok(format!("mock-{}", message.nonce))
+    }
+}
+
+// Admin functions
+#[update]
+pub fn set_bitcoin_price(price_usd: u128) -> Result<(), SatsumaError> {
+    // Only allow authorized principals
+    require_admin()?;
+    if price_usd == 0 {
+        return Err(SatsumaError::InvalidArgument("price must be positive".to_string()));
+    }
+    
+    // Manual override; the next oracle refresh replaces it
+    store_bitcoin_price(BitcoinPrice {
+        price_usd_micros: price_usd * MICROS_PER_USD,
+        updated_at: now_seconds(),
+        source: PriceSource::Manual,
     });
+    Ok(())
+}
+
+#[update]
+pub async fn refresh_bitcoin_price() -> Result<BitcoinPrice, SatsumaError> {
+    // Each XRC call costs cycles, so only admins may trigger it
+    require_admin()?;
+    refresh_price_from_oracle().await
+}
+
+#[update]
+pub fn add_admin(principal: Principal) -> Result<(), SatsumaError> {
+    require_admin()?;
+    insert_admin(principal);
+    Ok(())
+}
+
+#[update]
+pub fn remove_admin(principal: Principal) -> Result<(), SatsumaError> {
+    require_admin()?;
+    // Never remove the last admin; controllers remain a fallback but should not be required
+    if admin_count() <= 1 {
+        return Err(SatsumaError::InvalidArgument("cannot remove the last admin".to_string()));
+    }
+    ADMINS.with(|a| a.borrow_mut().remove(&principal));
+    // A removed admin can no longer hand its seat over
+    let handover = PENDING_ADMIN_HANDOVER.with(|p| p.borrow().get().handover.clone());
+    if handover.map_or(false, |handover| handover.from == principal) {
+        set_pending_admin_handover(None);
+    }
+    Ok(())
+}
+
+#[update]
+pub fn propose_admin_handover(new_admin: Principal) -> Result<(), SatsumaError> {
+    require_admin()?;
+    set_pending_admin_handover(Some(AdminHandover {
+        from: caller(),
+        to: new_admin,
+    }));
+    Ok(())
+}
+
+#[update]
+pub fn accept_admin_handover() -> Result<(), SatsumaError> {
+    let caller_id = caller();
+    let handover = PENDING_ADMIN_HANDOVER.with(|p| p.borrow().get().handover.clone());
+    let handover = match handover {
+        Some(handover) if handover.to == caller_id => handover,
+        _ => return Err(SatsumaError::Unauthorized),
+    };
+    // The proposer may have been removed since proposing; its offer lapses with it
+    if !ADMINS.with(|a| a.borrow().contains_key(&handover.from)) {
+        set_pending_admin_handover(None);
+        return Err(SatsumaError::Unauthorized);
+    }
+    
+    // The proposing admin hands its seat over to the new admin
+    ADMINS.with(|a| a.borrow_mut().remove(&handover.from));
+    insert_admin(handover.to);
+    set_pending_admin_handover(None);
+    Ok(())
+}
+
+#[update]
+pub fn cancel_admin_handover() -> Result<(), SatsumaError> {
+    require_admin()?;
+    set_pending_admin_handover(None);
+    Ok(())
+}
+
+#[query]
+pub fn get_admins() -> Result<Vec<Principal>, SatsumaError> {
+    Ok(ADMINS.with(|a| a.borrow().iter().map(|(principal, _)| principal).collect()))
+}
+
+#[query]
+pub fn get_pending_admin_handover() -> Result<Option<AdminHandover>, SatsumaError> {
+    Ok(PENDING_ADMIN_HANDOVER.with(|p| p.borrow().get().handover.clone()))
+}
+
+// Pays the caller's whole Bitcoin balance out as ckBTC (less the ledger fee) and gives up
+// their yield shares. Refuses, leaving every balance in place, if the transfer fails.
+#[update]
+pub async fn emergency_withdraw(principal: String) -> Result<BalanceResponse, SatsumaError> {
+    let caller_id = caller();
+    if caller_id.to_text() != principal {
+        return Err(SatsumaError::Unauthorized);
+    }
+    let ledger = ckbtc_ledger()?;
+    let fee = icrc1_fee(ledger).await?;
+    
+    settle_rewards(&principal);
+    let settled = BITCOIN_BALANCES.with(|btc| btc.borrow().get(&principal).unwrap_or(0));
+    let bitcoin_balance = settled.saturating_sub(fee);
+    if bitcoin_balance > 0 {
+        let to = Account {
+            owner: caller_id,
+            subaccount: None,
+        };
+        pay_out_ckbtc(ledger, &principal, to, bitcoin_balance, fee).await?;
+    }
+    
+    // Rewards accrued while awaiting are settled into the Bitcoin balance, not lost
+    let balance = yield_balance(&principal);
+    set_yield_balance(&principal, 0);
+    BALANCES.with(|b| b.borrow_mut().remove(&principal));
+    REWARD_CHECKPOINTS.with(|c| c.borrow_mut().remove(&principal));
+    
+    Ok(BalanceResponse {
+        principal,
+        balance,
+        bitcoin_balance,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_cdk::api::management_canister::bitcoin::Outpoint;
+
+    #[test]
+    fn balances_survive_upgrade() {
+        // State as a layout 1 build left it: balances but no reward index
+        STORED_LAYOUT_VERSION.with(|v| v.borrow_mut().set(1).unwrap());
+        BALANCES.with(|b| {
+            let mut balances = b.borrow_mut();
+            balances.insert("alice".to_string(), 1_500_000);
+            balances.insert("bob".to_string(), 250_000);
+        });
+        BITCOIN_BALANCES.with(|btc| btc.borrow_mut().insert("alice".to_string(), 4_200));
+        INJECTIVE_CONFIG.with(|cfg| {
+            cfg.borrow_mut()
+                .set(InjectiveConfig {
+                    contract_address: "inj1vault".to_string(),
+                    ..InjectiveConfig::default()
+                })
+                .unwrap();
+        });
+        YIELD_ACCUMULATOR.with(|acc| acc.borrow_mut().set(1_750_000).unwrap());
+        TOTAL_BITCOIN_CONVERTED.with(|total| total.borrow_mut().set(4_200).unwrap());
+        
+        // What `post_upgrade` runs before arming timers, which need a replica
+        migrate_stable_layout();
+        
+        // The new build reopens every structure over the same stable memory,
+        // exactly as the thread_local initialisers do
+        let layout_version: StableCell<u32, Memory> = StableCell::init(memory(LAYOUT_VERSION_MEMORY_ID), 0).unwrap();
+        let balances: StableBTreeMap<String, u128, Memory> = StableBTreeMap::init(memory(BALANCES_MEMORY_ID));
+        let bitcoin_balances: StableBTreeMap<String, u64, Memory> =
+            StableBTreeMap::init(memory(BITCOIN_BALANCES_MEMORY_ID));
+        let injective_config: StableCell<InjectiveConfig, Memory> =
+            StableCell::init(memory(INJECTIVE_CONFIG_MEMORY_ID), InjectiveConfig::default()).unwrap();
+        let yield_accumulator: StableCell<u128, Memory> =
+            StableCell::init(memory(YIELD_ACCUMULATOR_MEMORY_ID), 0).unwrap();
+        let total_bitcoin_converted: StableCell<u64, Memory> =
+            StableCell::init(memory(TOTAL_BITCOIN_CONVERTED_MEMORY_ID), 0).unwrap();
+        let reward_state: StableCell<RewardState, Memory> =
+            StableCell::init(memory(REWARD_STATE_MEMORY_ID), RewardState::default()).unwrap();
+        
+        assert_eq!(*layout_version.get(), LAYOUT_VERSION);
+        assert_eq!(balances.get(&"alice".to_string()), Some(1_500_000));
+        assert_eq!(balances.get(&"bob".to_string()), Some(250_000));
+        assert_eq!(bitcoin_balances.get(&"alice".to_string()), Some(4_200));
+        assert_eq!(injective_config.get().contract_address, "inj1vault");
+        assert_eq!(*yield_accumulator.get(), 1_750_000);
+        assert_eq!(*total_bitcoin_converted.get(), 4_200);
+        assert_eq!(reward_state.get().total_shares, 1_750_000);
+        assert_eq!(reward_state.get().btc_per_share, 0);
+        
+        // Carried-over balances share the next distribution pro rata
+        distribute_bitcoin_to_users(1_750);
+        assert_eq!(bitcoin_balance_of("alice"), 4_200 + 1_500);
+        assert_eq!(bitcoin_balance_of("bob"), 250);
+    }    
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+    
+    #[test]
+    fn p2wpkh_sighash_matches_bip143() {
+        // Native P2WPKH example from BIP143; the second input is the witness input
+        let tx = UnsignedTx {
+            version: 1,
+            inputs: vec![
+                TxInput {
+                    txid: unhex("fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f"),
+                    vout: 0,
+                    value: 625_000_000,
+                    sequence: 0xffff_ffee,
+                },
+                TxInput {
+                    txid: unhex("ef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a"),
+                    vout: 1,
+                    value: 600_000_000,
+                    sequence: 0xffff_ffff,
+                },
+            ],
+            outputs: vec![
+                TxOutput {
+                    value: 112_340_000,
+                    script_pubkey: unhex("76a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac"),
+                },
+                TxOutput {
+                    value: 223_450_000,
+                    script_pubkey: unhex("76a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac"),
+                },
+            ],
+            lock_time: 17,
+        };
+        assert_eq!(
+            hex(&tx.serialize(None)),
+            "0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffff\
+             ef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb2060000\
+             00001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe\
+             6a21b2d50ce2f0167faa815988ac11000000",
+        );
+        let public_key = unhex("025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee6357");
+        let pubkey_hash = hash160(&public_key);
+        assert_eq!(hex(&pubkey_hash), "1d0f172a0ecb48aee1be1f2687d2963ae33f71a1");
+        assert_eq!(
+            hex(&tx.p2wpkh_sighash(1, &pubkey_hash)),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670",
+        );
+    }
+    
+    #[test]
+    fn withdrawal_destinations_must_be_segwit_on_the_configured_network() {
+        let public_key = unhex("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        let address = p2wpkh_address(BitcoinNetwork::Mainnet, &public_key).unwrap();
+        assert_eq!(address, "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4");
+        assert_eq!(
+            script_pubkey_for_address(BitcoinNetwork::Mainnet, &address).unwrap(),
+            p2wpkh_script(&hash160(&public_key)),
+        );
+        assert_eq!(
+            hex(&script_pubkey_for_address(
+                BitcoinNetwork::Mainnet,
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+            )
+            .unwrap()),
+            "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        );
+        
+        assert!(script_pubkey_for_address(BitcoinNetwork::Regtest, &address).is_err());
+        assert!(script_pubkey_for_address(BitcoinNetwork::Mainnet, "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").is_err());
+    }
+    
+    #[test]
+    fn signatures_are_der_encoded_with_low_s() {
+        let mut compact = vec![0u8; 64];
+        compact[0] = 0x80;
+        compact[31] = 0x01;
+        // s = order - 1 is high, so it becomes 1
+        compact[32..].copy_from_slice(&SECP256K1_ORDER);
+        compact[63] -= 1;
+        
+        let mut expected = vec![0x30, 0x26, 0x02, 0x21, 0x00, 0x80];
+        expected.extend([0u8; 30]);
+        expected.extend([0x01, 0x02, 0x01, 0x01]);
+        assert_eq!(der_signature(&compact).unwrap(), expected);
+        
+        // A low s is kept as is
+        compact[32..].copy_from_slice(&[0x11; 32]);
+        let der = der_signature(&compact).unwrap();
+        assert_eq!(der[37..39], [0x02, 0x20]);
+        assert_eq!(der[39..], [0x11; 32]);
+        assert!(der_signature(&compact[..63]).is_err());
+    }
+    
+    fn utxo(tag: u8, value: u64) -> Utxo {
+        Utxo {
+            outpoint: Outpoint {
+                txid: vec![tag; 32],
+                vout: 0,
+            },
+            value,
+            height: 1,
+        }
+    }
     
-    ic_cdk::api::print(format!(
-        "Emergency withdrawal for {}: {} USDC, {} BTC",
-        principal, balance, bitcoin_balance
-    ));
-} 
\ No newline at end of file
+    fn native_balance(principal: &str) -> u64 {
+        NATIVE_BTC_BALANCES.with(|native| native.borrow().get(&principal.to_string()).unwrap_or(0))
+    }
+    
+    #[test]
+    fn native_withdrawals_debit_and_refund_the_balance() {
+        credit_native_btc_balance("alice", 150_000);
+        let utxos = vec![utxo(2, 50_000), utxo(1, 100_000)];
+        // One input and two outputs are 165 vbytes, so 330 sats at 2 sat/vB
+        let (inputs, fee) = select_native_utxos(utxos.clone(), 60_000, 2_000).unwrap();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].value, 100_000);
+        assert_eq!(fee, 330);
+        assert!(matches!(
+            select_native_utxos(utxos, 150_000, 2_000),
+            Err(SatsumaError::InsufficientBalance { .. })
+        ));
+        
+        let tx = UnsignedTx {
+            version: 2,
+            inputs,
+            outputs: vec![
+                TxOutput {
+                    value: 60_000,
+                    script_pubkey: p2wpkh_script(&[1; 20]),
+                },
+                TxOutput {
+                    value: 39_670,
+                    script_pubkey: p2wpkh_script(&[2; 20]),
+                },
+            ],
+            lock_time: 0,
+        };
+        let spent = outpoint_key(&[1; 32], 0);
+        let change = outpoint_key(&tx.txid(), 1);
+        reserve_native_withdrawal("alice", &tx, 60_330, 7).unwrap();
+        assert_eq!(native_balance("alice"), 89_670);
+        assert_eq!(NATIVE_BTC_HELD.with(|n| *n.borrow().get()), 89_670);
+        assert_eq!(SPENT_UTXOS.with(|s| s.borrow().get(&spent)), Some(7));
+        // Change is credited already, so a sync will not credit it again
+        assert_eq!(CREDITED_UTXOS.with(|c| c.borrow().get(&change)), Some(39_670));
+        assert!(matches!(
+            reserve_native_withdrawal("alice", &tx, 89_671, 8),
+            Err(SatsumaError::InsufficientBalance { .. })
+        ));
+        
+        // Signing or broadcasting failed
+        release_native_withdrawal("alice", &tx, 60_330);
+        assert_eq!(native_balance("alice"), 150_000);
+        assert_eq!(NATIVE_BTC_HELD.with(|n| *n.borrow().get()), 150_000);
+        assert!(!SPENT_UTXOS.with(|s| s.borrow().contains_key(&spent)));
+        assert!(!CREDITED_UTXOS.with(|c| c.borrow().contains_key(&change)));
+    }
+}
@@ -3,22 +3,166 @@
 // Written for Injective Chain using Astroport + Axelar GMP
 
 use cosmwasm_std::{
-    to_binary, Addr, Binary, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Response,
-    StdError, StdResult, Uint128, WasmMsg, Storage, BankMsg, Coin, SubMsg, QueryRequest, WasmQuery,
+    from_binary, to_binary, Addr, Api, Binary, CosmosMsg, Deps, DepsMut, Empty, Env, Event, MessageInfo, Order, Reply,
+    Response, StdError, StdResult, SubMsgResult, Timestamp, Uint128, Uint256, WasmMsg, Storage, Coin, SubMsg, QueryRequest, WasmQuery,
 };
-use cw2::set_contract_version;
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg};
 use cw_storage_plus::{Map, Item};
+use satsuma_messages::attribution::{self, YieldShare};
+use satsuma_messages::axelar::GmpEncoding;
+use satsuma_messages::{Envelope, IcpToInjective, InjectiveToIcp};
+use semver::Version;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
 
 // Constants
 const CONTRACT_NAME: &str = "injective_yield_vault";
-const CONTRACT_VERSION: &str = "1.0.0";
+const CONTRACT_VERSION: &str = "1.5.0";
+const BPS_DENOMINATOR: u64 = 10_000;
+const DEFAULT_MAX_SLIPPAGE_BPS: u64 = 50; // 0.5%
+const MAX_SLIPPAGE_BPS_LIMIT: u64 = 1_000; // 10%
+const DEFAULT_MAX_PEG_DEVIATION_BPS: u64 = 200; // 2%
+// 1 token at 6 decimals, used to probe the peg
+const PEG_PROBE_AMOUNT: u128 = 1_000_000;
+// Axelar chain name of the ICP canister
+const DEFAULT_ICP_CHAIN_NAME: &str = "icp";
+// Delay between proposing and applying a sensitive config change
+const DEFAULT_CONFIG_TIMELOCK_SECONDS: u64 = 2 * 24 * 60 * 60;
+// Fee caps: performance fee on skimmed yield, management fee per year on booked assets
+const MAX_PERFORMANCE_FEE_BPS: u64 = 2_000; // 20%
+const MAX_MANAGEMENT_FEE_BPS: u64 = 200; // 2% a year
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+// Fixed-point scale of the per-principal yield index
+const YIELD_INDEX_SCALE: u128 = 1_000_000_000_000_000_000;
+
+// Reply IDs for swap settlement
+const DEPOSIT_SWAP_REPLY_ID: u64 = 1;
+const WITHDRAW_SWAP_REPLY_ID: u64 = 2;
+const SKIM_SWAP_REPLY_ID: u64 = 3;
 
 // Storage
 static PRINCIPAL_BALANCES: Map<&Addr, Uint128> = Map::new("principal");
 static TOTAL_PRINCIPAL: Item<Uint128> = Item::new("total_principal");
 static CONFIG: Item<Config> = Item::new("config");
-static ICP_MANAGER: Item<Addr> = Item::new("icp_manager");
+// Access control: owner administers roles, roles gate operations
+static OWNER: Item<Addr> = Item::new("owner");
+static PENDING_OWNER: Item<Addr> = Item::new("pending_owner");
+static ROLE_MEMBERS: Map<(&str, &Addr), Empty> = Map::new("role_members");
+static PAUSE_STATE: Item<PauseState> = Item::new("pause_state");
+// Vault shares (ERC-4626 style); assets are denominated in nUSDC
+static SHARES: Map<&Addr, Uint128> = Map::new("shares");
+static TOTAL_SHARES: Item<Uint128> = Item::new("total_shares");
+// nUSDC booked against outstanding shares; anything held above this is yield
+static TOTAL_ASSETS: Item<Uint128> = Item::new("total_assets");
+// Context for the swap in flight, consumed by `reply`
+static PENDING_SWAP: Item<PendingSwap> = Item::new("pending_swap");
+// Nonce of the last message sent to ICP
+static OUTBOUND_NONCE: Item<u64> = Item::new("outbound_nonce");
+// Sensitive config change waiting out the timelock
+static PENDING_CONFIG: Item<PendingConfig> = Item::new("pending_config");
+static CONFIG_TIMELOCK_SECONDS: Item<u64> = Item::new("config_timelock_seconds");
+// Fees paid to the treasury out of skimmed yield
+static FEE_CONFIG: Item<FeeConfig> = Item::new("fee_config");
+static FEE_STATE: Item<FeeState> = Item::new("fee_state");
+// Per-depositor yield attribution; a skim only closes the period on YIELD_INDEX, and
+// each USER_YIELD entry books its share lazily at its next checkpoint
+static YIELD_INDEX: Item<YieldIndex> = Item::new("yield_index");
+static USER_YIELD: Map<&Addr, UserYield> = Map::new("user_yield");
+static SKIMMED_YIELD: Map<&Addr, Uint128> = Map::new("skimmed_yield");
+// (source chain, envelope nonce) of every executed ICP message, for replay protection
+static CONSUMED_NONCES: Map<(&str, u64), Empty> = Map::new("consumed_nonces");
+
+// Errors
+// Every message is prefixed with a stable code so relayers and frontends can
+// match on `Exxxx` without depending on the wording.
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error("E1000: {0}")]
+    Std(#[from] StdError),
+
+    #[error("E1001: unauthorized")]
+    Unauthorized {},
+
+    #[error("E1002: insufficient principal: requested {requested}, available {available}")]
+    InsufficientPrincipal { requested: Uint128, available: Uint128 },
+
+    #[error("E1003: no yield available")]
+    NoYield {},
+
+    #[error("E1004: vault is paused")]
+    Paused {},
+
+    #[error("E1005: invalid config: {reason}")]
+    InvalidConfig { reason: String },
+
+    #[error("E1006: slippage exceeded: expected at least {minimum}, received {received}")]
+    SlippageExceeded { minimum: Uint128, received: Uint128 },
+
+    #[error("E1007: bridge failure: {reason}")]
+    BridgeFailure { reason: String },
+
+    #[error("E1008: amount must be greater than zero")]
+    ZeroAmount {},
+
+    #[error("E1009: amount too small to mint shares")]
+    ZeroShares {},
+
+    #[error("E1010: vault has no assets backing its shares")]
+    NoAssets {},
+
+    #[error("E1011: another swap is pending settlement")]
+    SwapPending {},
+
+    #[error("E1012: unexpected reply id {id}")]
+    UnexpectedReply { id: u64 },
+
+    #[error("E1013: invalid swap quote")]
+    InvalidQuote {},
+
+    #[error("E1014: invalid migration: {reason}")]
+    InvalidMigration { reason: String },
+
+    #[error("E1015: swap quote deviates {deviation_bps} bps from 1:1, limit is {max_bps} bps")]
+    PegDeviation { deviation_bps: u64, max_bps: u64 },
+
+    #[error("E1016: config change is timelocked until {active_at}")]
+    ConfigTimelocked { active_at: Timestamp },
+
+    #[error("E1017: no pending config change")]
+    NoPendingConfig {},
+
+    #[error("E1018: message {nonce} from {source_chain} was already executed")]
+    DuplicateMessage { source_chain: String, nonce: u64 },
+}
+
+impl ContractError {
+    /// Stable numeric code, matching the `Exxxx` prefix of the message
+    pub fn code(&self) -> u32 {
+        match self {
+            ContractError::Std(_) => 1000,
+            ContractError::Unauthorized {} => 1001,
+            ContractError::InsufficientPrincipal { .. } => 1002,
+            ContractError::NoYield {} => 1003,
+            ContractError::Paused {} => 1004,
+            ContractError::InvalidConfig { .. } => 1005,
+            ContractError::SlippageExceeded { .. } => 1006,
+            ContractError::BridgeFailure { .. } => 1007,
+            ContractError::ZeroAmount {} => 1008,
+            ContractError::ZeroShares {} => 1009,
+            ContractError::NoAssets {} => 1010,
+            ContractError::SwapPending {} => 1011,
+            ContractError::UnexpectedReply { .. } => 1012,
+            ContractError::InvalidQuote {} => 1013,
+            ContractError::InvalidMigration { .. } => 1014,
+            ContractError::PegDeviation { .. } => 1015,
+            ContractError::ConfigTimelocked { .. } => 1016,
+            ContractError::NoPendingConfig {} => 1017,
+            ContractError::DuplicateMessage { .. } => 1018,
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Config {
@@ -30,6 +174,93 @@ pub struct Config {
     pub yield_collector: Addr,
     pub pool_id_usdc_nusdc: u64,
     pub pool_id_nusdc_usdc: u64,
+    /// Default tolerance below the simulated quote for every swap, in basis points
+    pub max_slippage_bps: u64,
+    /// Circuit breaker bound on how far a swap quote may drift from 1:1, in basis points
+    pub max_peg_deviation_bps: u64,
+    /// Axelar chain name of ICP; inbound GMP calls must come from this chain and `icp_canister_id`
+    pub icp_chain_name: String,
+    /// Encoding of outbound GMP payloads
+    pub gmp_encoding: GmpEncoding,
+    /// Native coin attached to every outbound GMP call to prepay Axelar gas; the vault
+    /// must hold it
+    #[serde(default)]
+    pub axelar_gas_fee: Option<Coin>,
+}
+
+// Config fields that can redirect funds or forge inbound messages; changed only
+// through `ProposeConfig` → timelock → `ApplyConfig`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SensitiveConfig {
+    pub token_usdc: String,
+    pub token_nusdc: String,
+    pub astroport_router: String,
+    pub axelar_gateway: String,
+    pub icp_canister_id: String,
+    pub icp_chain_name: String,
+}
+
+impl From<&Config> for SensitiveConfig {
+    fn from(config: &Config) -> Self {
+        Self {
+            token_usdc: config.token_usdc.clone(),
+            token_nusdc: config.token_nusdc.clone(),
+            astroport_router: config.astroport_router.clone(),
+            axelar_gateway: config.axelar_gateway.clone(),
+            icp_canister_id: config.icp_canister_id.clone(),
+            icp_chain_name: config.icp_chain_name.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FeeConfig {
+    pub treasury: Addr,
+    /// Share of each skim's yield, in basis points
+    pub performance_fee_bps: u64,
+    /// Yearly rate on booked assets, accrued per second and paid out of skimmed yield
+    pub management_fee_bps: u64,
+}
+
+// Management fee accrued but not yet paid; principal is never charged, so a
+// skim with too little yield leaves the rest outstanding
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FeeState {
+    pub management_fee_outstanding: Uint128,
+    pub last_accrued_at: Timestamp,
+}
+
+// Cumulative nUSDC yield per unit of principal, scaled by `YIELD_INDEX_SCALE`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct YieldIndex {
+    pub yield_per_principal: Uint256,
+    /// Yield held above booked assets that the index already covers
+    pub indexed_yield: Uint128,
+    /// Number of skims so far
+    #[serde(default)]
+    pub skims: u64,
+    /// `yield_per_principal` at the last skim; yield accrued up to here has been skimmed
+    #[serde(default)]
+    pub skimmed_per_principal: Uint256,
+}
+
+// A missing entry is an account checkpointed when the index was zero
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct UserYield {
+    /// `YieldIndex::yield_per_principal` at the last checkpoint
+    pub index: Uint256,
+    pub unskimmed: Uint128,
+    /// `YieldIndex::skims` at the last checkpoint
+    #[serde(default)]
+    pub skims: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PendingConfig {
+    pub config: SensitiveConfig,
+    pub proposed_at: Timestamp,
+    /// Earliest block time at which `ApplyConfig` succeeds
+    pub active_at: Timestamp,
 }
 
 // Instantiate Msg
@@ -43,23 +274,228 @@ pub struct InstantiateMsg {
     pub yield_collector: String,
     pub pool_id_usdc_nusdc: u64,
     pub pool_id_nusdc_usdc: u64,
+    /// Granted the bridge role
     pub icp_manager: String,
+    pub max_slippage_bps: Option<u64>,
+    pub max_peg_deviation_bps: Option<u64>,
+    /// Defaults to "icp"
+    pub icp_chain_name: Option<String>,
+    /// Defaults to JSON
+    pub gmp_encoding: Option<GmpEncoding>,
+    /// Defaults to no prepaid gas
+    pub axelar_gas_fee: Option<Coin>,
+    /// Defaults to 48 hours
+    pub config_timelock_seconds: Option<u64>,
+    /// Fee recipient, defaults to the owner
+    pub treasury: Option<String>,
+    /// Defaults to 0
+    pub performance_fee_bps: Option<u64>,
+    /// Defaults to 0
+    pub management_fee_bps: Option<u64>,
+    /// Defaults to the instantiating address
+    pub owner: Option<String>,
+}
+
+// Migrate Msg
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MigrateMsg {
+    /// Required when migrating from 1.0, which had no owner
+    pub owner: Option<String>,
+}
+
+// Config layout written by 1.0.x, kept to migrate stored state
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct ConfigV1_0 {
+    token_usdc: String,
+    token_nusdc: String,
+    astroport_router: String,
+    axelar_gateway: String,
+    icp_canister_id: String,
+    yield_collector: Addr,
+    pool_id_usdc_nusdc: u64,
+    pool_id_nusdc_usdc: u64,
+}
+
+// Config layout written by 1.1.x
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct ConfigV1_1 {
+    token_usdc: String,
+    token_nusdc: String,
+    astroport_router: String,
+    axelar_gateway: String,
+    icp_canister_id: String,
+    yield_collector: Addr,
+    pool_id_usdc_nusdc: u64,
+    pool_id_nusdc_usdc: u64,
+    max_slippage_bps: u64,
+    max_peg_deviation_bps: u64,
 }
 
 // Execute Msg
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum ExecuteMsg {
-    Deposit { amount: Uint128 },
-    SkimYield {},
-    SetIcpManager { manager: String },
+    /// Pull `amount` USDC from the sender via a pre-approved allowance
+    Deposit { amount: Uint128, max_slippage_bps: Option<u64> },
+    /// CW20 `Send` hook: USDC sent to the vault with an embedded `DepositMsg`
+    #[serde(rename = "receive")]
+    Receive(Cw20ReceiveMsg),
+    Withdraw { amount: Uint128, max_slippage_bps: Option<u64> },
+    /// The Merkle root of the depositors' shares is computed when the skim settles
+    SkimYield { max_slippage_bps: Option<u64> },
     ExecuteFromIcp { action: IcpAction },
+    /// Versioned `satsuma-messages` envelope relayed from ICP
+    ExecuteIcpMessage { envelope: Envelope<IcpToInjective> },
+    /// Axelar GMP call; the payload is a JSON `satsuma-messages` envelope
+    AxelarExecute { source_chain: String, source_address: String, payload: Binary },
+    /// Owner nominates a new owner, who must accept
+    TransferOwnership { new_owner: String },
+    AcceptOwnership {},
+    GrantRole { role: Role, address: String },
+    RevokeRole { role: Role, address: String },
+    /// Guardian or owner
+    Pause { scope: PauseScope },
+    /// Owner only
+    Unpause { scope: PauseScope },
+    /// Guardian or keeper: probe the pool and trip the circuit breaker on a depeg
+    CheckPeg {},
+    /// Owner only; changes the non-sensitive config fields immediately, even while
+    /// cross-chain actions are paused
+    UpdateConfig { config: Config },
+    /// Owner queues a change of the sensitive config fields behind the timelock
+    ProposeConfig { config: SensitiveConfig },
+    /// Owner applies the pending change once the timelock has passed
+    ApplyConfig {},
+    /// Guardian or owner drops the pending change
+    CancelConfig {},
+    /// Owner only; management fee accrued so far is kept at the old rate
+    SetFeeConfig { treasury: String, performance_fee_bps: u64, management_fee_bps: u64 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseScope {
+    Deposits,
+    Skims,
+    CrossChain,
+    /// Full halt, withdrawals included
+    All,
+}
+
+impl PauseScope {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PauseScope::Deposits => "deposits",
+            PauseScope::Skims => "skims",
+            PauseScope::CrossChain => "cross_chain",
+            PauseScope::All => "all",
+        }
+    }
+}
+
+// Withdrawals are only blocked by a full halt
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct PauseState {
+    pub deposits: bool,
+    pub skims: bool,
+    pub cross_chain: bool,
+    pub halted: bool,
+}
+
+impl PauseState {
+    fn set(&mut self, scope: PauseScope, paused: bool) {
+        match scope {
+            PauseScope::Deposits => self.deposits = paused,
+            PauseScope::Skims => self.skims = paused,
+            PauseScope::CrossChain => self.cross_chain = paused,
+            PauseScope::All => self.halted = paused,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can pause the vault
+    Guardian,
+    /// Can skim yield
+    Keeper,
+    /// Can relay cross-chain actions from ICP
+    Bridge,
+}
+
+impl Role {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Role::Guardian => "guardian",
+            Role::Keeper => "keeper",
+            Role::Bridge => "bridge",
+        }
+    }
+}
+
+// Swap awaiting settlement in `reply`; balances are snapshotted before dispatch
+// so the reply can measure exactly what the swap delivered
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingSwap {
+    Deposit {
+        depositor: Addr,
+        amount: Uint128,
+        nusdc_before: Uint128,
+        minimum_receive: Uint128,
+    },
+    Withdraw {
+        user: Addr,
+        usdc_before: Uint128,
+        minimum_receive: Uint128,
+    },
+    Skim(PendingSkim),
+}
+
+// Skim awaiting settlement; its fees and yield index accrual are booked only once the
+// swap succeeds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PendingSkim {
+    pub recipient: String,
+    /// nUSDC swapped, after fees
+    pub yield_amount: Uint128,
+    /// Vault nUSDC balance when the skim was dispatched
+    pub nusdc_held: Uint128,
+    pub performance_fee: Uint128,
+    pub management_fee: Uint128,
+    pub usdc_before: Uint128,
+    pub minimum_receive: Uint128,
+    pub action: String,
+}
+
+// Embedded in `Cw20ReceiveMsg::msg` when sending USDC to the vault
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DepositMsg {
+    /// Credit principal to this address instead of the CW20 sender
+    pub recipient: Option<String>,
+    /// Overrides `Config::max_slippage_bps` for this deposit
+    pub max_slippage_bps: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum IcpAction {
     Deposit { user: String, amount: Uint128 },
-    SkimYield { recipient: String },
-    UpdateConfig { config: Config },
+    SkimYield { recipient: String, max_slippage_bps: Option<u64> },
+}
+
+impl From<IcpToInjective> for IcpAction {
+    fn from(message: IcpToInjective) -> Self {
+        match message {
+            IcpToInjective::Deposit { user, amount } => IcpAction::Deposit {
+                user,
+                amount: Uint128::new(amount),
+            },
+            IcpToInjective::SkimYield { recipient, max_slippage_bps } => IcpAction::SkimYield {
+                recipient,
+                max_slippage_bps,
+            },
+        }
+    }
 }
 
 // Query Msg
@@ -70,44 +506,76 @@ pub enum QueryMsg {
     Principal { address: String },
     TotalPrincipal {},
     NusdcBalance {},
-    IcpManager {},
+    Roles {},
+    PauseState {},
+    Shares { address: String },
+    TotalShares {},
+    TotalAssets {},
+    ConvertToShares { assets: Uint128 },
+    ConvertToAssets { shares: Uint128 },
+    PreviewDeposit { assets: Uint128 },
+    PreviewWithdraw { assets: Uint128 },
+    PendingConfig {},
+    FeeConfig {},
+    /// Gross nUSDC yield attributed to a depositor
+    UserYield { address: String },
 }
 
-// CW20 helpers
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct Cw20ExecuteMsg {
-    pub transfer: Option<TransferMsg>,
-    pub send: Option<SendMsg>,
-    pub approve: Option<ApproveMsg>,
+pub struct PendingConfigResponse {
+    pub pending: Option<PendingConfig>,
+    pub timelock_seconds: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct TransferMsg {
-    pub recipient: String,
-    pub amount: Uint128,
+pub struct UserYieldResponse {
+    /// Accrued since the last skim, up to the current block
+    pub unskimmed: Uint128,
+    /// Attributed in past skims
+    pub skimmed: Uint128,
+    pub lifetime: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SendMsg {
-    pub contract: String,
-    pub amount: Uint128,
-    pub msg: Binary,
+pub struct FeeConfigResponse {
+    pub config: FeeConfig,
+    /// Includes accrual up to the current block
+    pub management_fee_outstanding: Uint128,
+    pub max_performance_fee_bps: u64,
+    pub max_management_fee_bps: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct ApproveMsg {
-    pub spender: String,
-    pub amount: Uint128,
+pub struct RolesResponse {
+    pub owner: Addr,
+    pub pending_owner: Option<Addr>,
+    pub guardians: Vec<Addr>,
+    pub keepers: Vec<Addr>,
+    pub bridges: Vec<Addr>,
+}
+
+// Astroport Router Queries
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AstroportRouterQueryMsg {
+    SimulateSwapOperations {
+        offer_amount: Uint128,
+        operations: Vec<SwapOperation>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct Cw20QueryMsg {
-    pub balance: Option<BalanceQuery>,
+#[serde(rename_all = "snake_case")]
+pub enum SwapOperation {
+    AstroSwap {
+        offer_asset_info: AssetInfo,
+        ask_asset_info: AssetInfo,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct BalanceQuery {
-    pub address: String,
+pub struct SimulateSwapOperationsResponse {
+    pub amount: Uint128,
 }
 
 // Astroport Router Messages
@@ -121,6 +589,7 @@ pub struct AstroportSwap {
     pub offer_asset: OfferAsset,
     pub ask_asset_info: AssetInfo,
     pub minimum_receive: Option<Uint128>,
+    pub to: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -145,20 +614,14 @@ pub struct AxelarGmpMsg {
     pub amount: Uint128,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct IcpPayload {
-    pub principal: String,
-    pub amount: Uint128,
-    pub action: String,
-}
 
 // Instantiate
 pub fn instantiate(
     deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
+    env: Env,
+    info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     let config = Config {
         token_usdc: msg.token_usdc,
@@ -169,25 +632,216 @@ pub fn instantiate(
         yield_collector: deps.api.addr_validate(&msg.yield_collector)?,
         pool_id_usdc_nusdc: msg.pool_id_usdc_nusdc,
         pool_id_nusdc_usdc: msg.pool_id_nusdc_usdc,
+        max_slippage_bps: msg.max_slippage_bps.unwrap_or(DEFAULT_MAX_SLIPPAGE_BPS),
+        max_peg_deviation_bps: msg
+            .max_peg_deviation_bps
+            .unwrap_or(DEFAULT_MAX_PEG_DEVIATION_BPS),
+        icp_chain_name: msg.icp_chain_name.unwrap_or_else(|| DEFAULT_ICP_CHAIN_NAME.to_string()),
+        gmp_encoding: msg.gmp_encoding.unwrap_or_default(),
+        axelar_gas_fee: msg.axelar_gas_fee,
     };
+    validate_config(deps.api, &config)?;
     CONFIG.save(deps.storage, &config)?;
+    CONFIG_TIMELOCK_SECONDS.save(
+        deps.storage,
+        &msg.config_timelock_seconds.unwrap_or(DEFAULT_CONFIG_TIMELOCK_SECONDS),
+    )?;
+    PAUSE_STATE.save(deps.storage, &PauseState::default())?;
     TOTAL_PRINCIPAL.save(deps.storage, &Uint128::zero())?;
-    ICP_MANAGER.save(deps.storage, &deps.api.addr_validate(&msg.icp_manager)?)?;
+    TOTAL_SHARES.save(deps.storage, &Uint128::zero())?;
+    TOTAL_ASSETS.save(deps.storage, &Uint128::zero())?;
+    YIELD_INDEX.save(deps.storage, &YieldIndex::default())?;
+    let owner = match msg.owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender,
+    };
+    OWNER.save(deps.storage, &owner)?;
+    let fee_config = FeeConfig {
+        treasury: match msg.treasury {
+            Some(treasury) => deps.api.addr_validate(&treasury)?,
+            None => owner,
+        },
+        performance_fee_bps: msg.performance_fee_bps.unwrap_or(0),
+        management_fee_bps: msg.management_fee_bps.unwrap_or(0),
+    };
+    validate_fee_config(&fee_config)?;
+    FEE_CONFIG.save(deps.storage, &fee_config)?;
+    FEE_STATE.save(deps.storage, &FeeState {
+        management_fee_outstanding: Uint128::zero(),
+        last_accrued_at: env.block.time,
+    })?;
+    let icp_manager = deps.api.addr_validate(&msg.icp_manager)?;
+    ROLE_MEMBERS.save(deps.storage, (Role::Bridge.as_str(), &icp_manager), &Empty {})?;
+    ROLE_MEMBERS.save(deps.storage, (Role::Keeper.as_str(), &config.yield_collector), &Empty {})?;
     Ok(Response::default())
 }
 
+// Migrate: only upgrades from an older version of this contract are accepted
+pub fn migrate(
+    deps: DepsMut,
+    env: Env,
+    msg: MigrateMsg,
+) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::InvalidMigration {
+            reason: format!("cannot migrate from contract {}", stored.contract),
+        });
+    }
+    let stored_version = parse_version(&stored.version)?;
+    let new_version = parse_version(CONTRACT_VERSION)?;
+    if stored_version > new_version {
+        return Err(ContractError::InvalidMigration {
+            reason: format!("cannot downgrade from {} to {}", stored.version, CONTRACT_VERSION),
+        });
+    }
+    
+    // Storage migrations, applied in order
+    if stored_version < Version::new(1, 1, 0) {
+        let owner = msg.owner.ok_or_else(|| ContractError::InvalidMigration {
+            reason: "owner is required when migrating from 1.0".to_string(),
+        })?;
+        let owner = deps.api.addr_validate(&owner)?;
+        migrate_v1_0_to_v1_1(deps.storage, owner)?;
+    }
+    if stored_version < Version::new(1, 2, 0) {
+        migrate_v1_1_to_v1_2(deps.storage)?;
+    }
+    if stored_version < Version::new(1, 3, 0) {
+        CONFIG_TIMELOCK_SECONDS.save(deps.storage, &DEFAULT_CONFIG_TIMELOCK_SECONDS)?;
+    }
+    if stored_version < Version::new(1, 4, 0) {
+        // Fees start at zero, paid to the owner until configured
+        let treasury = OWNER.load(deps.storage)?;
+        FEE_CONFIG.save(deps.storage, &FeeConfig {
+            treasury,
+            performance_fee_bps: 0,
+            management_fee_bps: 0,
+        })?;
+        FEE_STATE.save(deps.storage, &FeeState {
+            management_fee_outstanding: Uint128::zero(),
+            last_accrued_at: env.block.time,
+        })?;
+    }
+    if stored_version < Version::new(1, 5, 0) {
+        migrate_v1_4_to_v1_5(deps.storage)?;
+    }
+    
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", stored.version)
+        .add_attribute("to_version", CONTRACT_VERSION))
+}
+
+// 1.0 → 1.1: extend Config with defaults, seed share accounting 1:1 from principal
+// and replace the ICP manager with roles
+fn migrate_v1_0_to_v1_1(storage: &mut dyn Storage, owner: Addr) -> Result<(), ContractError> {
+    let legacy = Item::<ConfigV1_0>::new("config").load(storage)?;
+    let config = ConfigV1_1 {
+        token_usdc: legacy.token_usdc,
+        token_nusdc: legacy.token_nusdc,
+        astroport_router: legacy.astroport_router,
+        axelar_gateway: legacy.axelar_gateway,
+        icp_canister_id: legacy.icp_canister_id,
+        yield_collector: legacy.yield_collector,
+        pool_id_usdc_nusdc: legacy.pool_id_usdc_nusdc,
+        pool_id_nusdc_usdc: legacy.pool_id_nusdc_usdc,
+        max_slippage_bps: DEFAULT_MAX_SLIPPAGE_BPS,
+        max_peg_deviation_bps: DEFAULT_MAX_PEG_DEVIATION_BPS,
+    };
+    Item::<ConfigV1_1>::new("config").save(storage, &config)?;
+    PAUSE_STATE.save(storage, &PauseState::default())?;
+    
+    // 1.0 booked USDC principal as if it were nUSDC, so shares start at par
+    let principals = PRINCIPAL_BALANCES
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (addr, principal) in principals {
+        SHARES.save(storage, &addr, &principal)?;
+    }
+    let total_principal = TOTAL_PRINCIPAL.load(storage)?;
+    TOTAL_SHARES.save(storage, &total_principal)?;
+    TOTAL_ASSETS.save(storage, &total_principal)?;
+    
+    // The ICP manager becomes the bridge and the yield collector keeps skimming as keeper
+    let icp_manager_item: Item<Addr> = Item::new("icp_manager");
+    let icp_manager = icp_manager_item.load(storage)?;
+    icp_manager_item.remove(storage);
+    OWNER.save(storage, &owner)?;
+    ROLE_MEMBERS.save(storage, (Role::Bridge.as_str(), &icp_manager), &Empty {})?;
+    ROLE_MEMBERS.save(storage, (Role::Keeper.as_str(), &config.yield_collector), &Empty {})?;
+    Ok(())
+}
+
+// 1.1 → 1.2: add the Axelar source chain and payload encoding to Config
+fn migrate_v1_1_to_v1_2(storage: &mut dyn Storage) -> Result<(), ContractError> {
+    let legacy = Item::<ConfigV1_1>::new("config").load(storage)?;
+    let config = Config {
+        token_usdc: legacy.token_usdc,
+        token_nusdc: legacy.token_nusdc,
+        astroport_router: legacy.astroport_router,
+        axelar_gateway: legacy.axelar_gateway,
+        icp_canister_id: legacy.icp_canister_id,
+        yield_collector: legacy.yield_collector,
+        pool_id_usdc_nusdc: legacy.pool_id_usdc_nusdc,
+        pool_id_nusdc_usdc: legacy.pool_id_nusdc_usdc,
+        max_slippage_bps: legacy.max_slippage_bps,
+        max_peg_deviation_bps: legacy.max_peg_deviation_bps,
+        icp_chain_name: DEFAULT_ICP_CHAIN_NAME.to_string(),
+        gmp_encoding: GmpEncoding::Json,
+        axelar_gas_fee: None,
+    };
+    CONFIG.save(storage, &config)?;
+    Ok(())
+}
+
+// 1.4 → 1.5: start the yield index at zero. Depositors have no `USER_YIELD` entry yet,
+// which reads as checkpointed at zero, so each is settled lazily at its next checkpoint
+fn migrate_v1_4_to_v1_5(storage: &mut dyn Storage) -> Result<(), ContractError> {
+    YIELD_INDEX.save(storage, &YieldIndex::default())?;
+    Ok(())
+}
+
 // Execute
 pub fn execute(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Deposit { amount } => execute_deposit(deps, env, info, amount),
-        ExecuteMsg::SkimYield {} => execute_skim(deps, env, info),
-        ExecuteMsg::SetIcpManager { manager } => execute_set_icp_manager(deps, info, manager),
+        ExecuteMsg::Deposit { amount, max_slippage_bps } => {
+            execute_deposit(deps, env, info, amount, max_slippage_bps)
+        },
+        ExecuteMsg::Receive(cw20_msg) => execute_receive(deps, env, info, cw20_msg),
+        ExecuteMsg::Withdraw { amount, max_slippage_bps } => {
+            execute_withdraw(deps, env, info, amount, max_slippage_bps)
+        },
+        ExecuteMsg::SkimYield { max_slippage_bps } => {
+            execute_skim(deps, env, info, max_slippage_bps)
+        },
         ExecuteMsg::ExecuteFromIcp { action } => execute_from_icp(deps, env, info, action),
+        ExecuteMsg::ExecuteIcpMessage { envelope } => execute_icp_message(deps, env, info, envelope),
+        ExecuteMsg::AxelarExecute { source_chain, source_address, payload } => {
+            execute_axelar(deps, env, info, source_chain, source_address, payload)
+        },
+        ExecuteMsg::TransferOwnership { new_owner } => {
+            execute_transfer_ownership(deps, info, new_owner)
+        },
+        ExecuteMsg::AcceptOwnership {} => execute_accept_ownership(deps, info),
+        ExecuteMsg::GrantRole { role, address } => execute_grant_role(deps, info, role, address),
+        ExecuteMsg::RevokeRole { role, address } => execute_revoke_role(deps, info, role, address),
+        ExecuteMsg::Pause { scope } => execute_pause(deps, info, scope),
+        ExecuteMsg::Unpause { scope } => execute_unpause(deps, info, scope),
+        ExecuteMsg::CheckPeg {} => execute_check_peg(deps, info),
+        ExecuteMsg::UpdateConfig { config } => execute_update_config(deps, info, config),
+        ExecuteMsg::ProposeConfig { config } => execute_propose_config(deps, env, info, config),
+        ExecuteMsg::ApplyConfig {} => execute_apply_config(deps, env, info),
+        ExecuteMsg::CancelConfig {} => execute_cancel_config(deps, info),
+        ExecuteMsg::SetFeeConfig { treasury, performance_fee_bps, management_fee_bps } => {
+            execute_set_fee_config(deps, env, info, treasury, performance_fee_bps, management_fee_bps)
+        },
     }
 }
 
@@ -196,8 +850,8 @@ pub fn query(
     deps: Deps,
     env: Env,
     msg: QueryMsg,
-) -> StdResult<Binary> {
-    match msg {
+) -> Result<Binary, ContractError> {
+    let res = match msg {
         QueryMsg::Config {} => to_binary(&CONFIG.load(deps.storage)?),
         QueryMsg::Principal { address } => {
             let addr = deps.api.addr_validate(&address)?;
@@ -210,68 +864,312 @@ pub fn query(
             let bal = query_cw20_balance(
                 deps,
                 &config.token_nusdc,
-                &env.contract.address.to_string(),
+                env.contract.address.as_str(),
             )?;
             to_binary(&bal)
         },
-        QueryMsg::IcpManager {} => to_binary(&ICP_MANAGER.load(deps.storage)?),
-    }
+        QueryMsg::Roles {} => to_binary(&RolesResponse {
+            owner: OWNER.load(deps.storage)?,
+            pending_owner: PENDING_OWNER.may_load(deps.storage)?,
+            guardians: role_members(deps, Role::Guardian)?,
+            keepers: role_members(deps, Role::Keeper)?,
+            bridges: role_members(deps, Role::Bridge)?,
+        }),
+        QueryMsg::PauseState {} => to_binary(&PAUSE_STATE.load(deps.storage)?),
+        QueryMsg::Shares { address } => {
+            let addr = deps.api.addr_validate(&address)?;
+            let shares = SHARES.may_load(deps.storage, &addr)?.unwrap_or_default();
+            to_binary(&shares)
+        },
+        QueryMsg::TotalShares {} => to_binary(&TOTAL_SHARES.load(deps.storage)?),
+        QueryMsg::TotalAssets {} => {
+            let config = CONFIG.load(deps.storage)?;
+            let (total_assets, _) = vault_totals(deps, &env, &config)?;
+            to_binary(&total_assets)
+        },
+        QueryMsg::ConvertToShares { assets } | QueryMsg::PreviewDeposit { assets } => {
+            let config = CONFIG.load(deps.storage)?;
+            let (total_assets, total_shares) = vault_totals(deps, &env, &config)?;
+            to_binary(&assets_to_shares(assets, total_assets, total_shares, false)?)
+        },
+        QueryMsg::ConvertToAssets { shares } => {
+            let config = CONFIG.load(deps.storage)?;
+            let (total_assets, total_shares) = vault_totals(deps, &env, &config)?;
+            to_binary(&shares_to_assets(shares, total_assets, total_shares, false)?)
+        },
+        QueryMsg::PreviewWithdraw { assets } => {
+            let config = CONFIG.load(deps.storage)?;
+            let (total_assets, total_shares) = vault_totals(deps, &env, &config)?;
+            to_binary(&assets_to_shares(assets, total_assets, total_shares, true)?)
+        },
+        QueryMsg::PendingConfig {} => to_binary(&PendingConfigResponse {
+            pending: PENDING_CONFIG.may_load(deps.storage)?,
+            timelock_seconds: CONFIG_TIMELOCK_SECONDS.load(deps.storage)?,
+        }),
+        QueryMsg::UserYield { address } => {
+            let addr = deps.api.addr_validate(&address)?;
+            let config = CONFIG.load(deps.storage)?;
+            let held = query_cw20_balance(deps, &config.token_nusdc, env.contract.address.as_str())?;
+            let index = next_yield_index(
+                &YIELD_INDEX.load(deps.storage)?,
+                held,
+                TOTAL_ASSETS.load(deps.storage)?,
+                TOTAL_PRINCIPAL.load(deps.storage)?,
+            );
+            let principal = PRINCIPAL_BALANCES.may_load(deps.storage, &addr)?.unwrap_or_default();
+            let user = USER_YIELD.may_load(deps.storage, &addr)?.unwrap_or_default();
+            let (user, newly_skimmed) = settle_user_yield(&user, principal, &index)?;
+            let skimmed = SKIMMED_YIELD.may_load(deps.storage, &addr)?.unwrap_or_default() + newly_skimmed;
+            to_binary(&UserYieldResponse {
+                unskimmed: user.unskimmed,
+                skimmed,
+                lifetime: skimmed + user.unskimmed,
+            })
+        },
+        QueryMsg::FeeConfig {} => {
+            let config = FEE_CONFIG.load(deps.storage)?;
+            let fee_state = FEE_STATE.load(deps.storage)?;
+            let booked = TOTAL_ASSETS.load(deps.storage)?;
+            let accrued = management_fee_accrued(&config, &fee_state, booked, env.block.time)?;
+            to_binary(&FeeConfigResponse {
+                config,
+                management_fee_outstanding: fee_state.management_fee_outstanding + accrued,
+                max_performance_fee_bps: MAX_PERFORMANCE_FEE_BPS,
+                max_management_fee_bps: MAX_MANAGEMENT_FEE_BPS,
+            })
+        },
+    }?;
+    Ok(res)
 }
 
-// Handle deposit: USDC → swap → nUSDC → update principal
+// Handle deposit: pull USDC via TransferFrom → swap → nUSDC → update principal
 fn execute_deposit(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     amount: Uint128,
-) -> StdResult<Response> {
+    max_slippage_bps: Option<u64>,
+) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
+    let pull_msg = build_usdc_transfer_from_msg(&config, &env, info.sender.to_string(), amount)?;
     
-    // 1. Transfer USDC from sender to contract
-    let transfer_msg = Cw20ExecuteMsg {
-        transfer: Some(TransferMsg {
-            recipient: env.contract.address.to_string(),
-            amount,
-        }),
-        send: None,
-        approve: None,
+    deposit_usdc(deps, &env, &config, &info.sender, amount, max_slippage_bps, Some(pull_msg))
+}
+
+// Handle CW20 Send hook: USDC has already arrived → swap → nUSDC → update principal
+fn execute_receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    // Only the USDC token contract can tell us tokens arrived
+    if info.sender.as_str() != config.token_usdc {
+        return Err(ContractError::Unauthorized {});
+    }
+    
+    let deposit_msg: DepositMsg = from_binary(&cw20_msg.msg)?;
+    let depositor = match deposit_msg.recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => deps.api.addr_validate(&cw20_msg.sender)?,
     };
     
-    // 2. Approve Astroport router to spend USDC
-    let approve_msg = Cw20ExecuteMsg {
-        transfer: None,
-        send: None,
-        approve: Some(ApproveMsg {
-            spender: config.astroport_router.clone(),
-            amount,
-        }),
+    deposit_usdc(
+        deps,
+        &env,
+        &config,
+        &depositor,
+        cw20_msg.amount,
+        deposit_msg.max_slippage_bps,
+        None,
+    )
+}
+
+// Shared deposit logic: approve router and dispatch the USDC → nUSDC swap.
+// Shares and principal are credited in `reply` once the swap has settled.
+// `pull_msg` moves the USDC into the vault first when it is not already held.
+fn deposit_usdc(
+    deps: DepsMut,
+    env: &Env,
+    config: &Config,
+    depositor: &Addr,
+    amount: Uint128,
+    max_slippage_bps: Option<u64>,
+    pull_msg: Option<CosmosMsg>,
+) -> Result<Response, ContractError> {
+    let pause = PAUSE_STATE.load(deps.storage)?;
+    if pause.deposits || pause.halted {
+        return Err(ContractError::Paused {});
+    }
+    if amount.is_zero() {
+        return Err(ContractError::ZeroAmount {});
+    }
+    let slippage_bps = resolve_slippage_bps(config, max_slippage_bps)?;
+    
+    // 1. Approve Astroport router to spend USDC
+    let approve_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: config.astroport_router.clone(),
+        amount,
+        expires: None,
     };
     
-    // 3. Swap USDC → nUSDC via Astroport router
-    let swap_msg = build_astroport_swap_msg(&config, amount);
+    // 2. Swap USDC → nUSDC via Astroport router, bounded by the simulated quote.
+    // Depositing into a depegged pool is refused outright.
+    let deviation_bps = probe_peg_deviation_bps(deps.as_ref(), config, &config.token_usdc, &config.token_nusdc)?;
+    if deviation_bps > config.max_peg_deviation_bps {
+        return Err(ContractError::PegDeviation {
+            deviation_bps,
+            max_bps: config.max_peg_deviation_bps,
+        });
+    }
+    let (_, minimum_receive) = simulate_swap(
+        deps.as_ref(),
+        config,
+        &config.token_usdc,
+        &config.token_nusdc,
+        amount,
+        slippage_bps,
+    )?;
+    let swap_msg = build_astroport_swap_msg(config, amount, minimum_receive);
     
-    // 4. Update principal
-    PRINCIPAL_BALANCES.update(deps.storage, &info.sender, |val| {
-        Ok(val.unwrap_or_default() + amount)
-    })?;
-    TOTAL_PRINCIPAL.update(deps.storage, |val| {
-        Ok(val.unwrap_or_default() + amount)
+    // 3. Snapshot nUSDC so the reply can credit exactly what arrived
+    let nusdc_before = query_cw20_balance(
+        deps.as_ref(),
+        &config.token_nusdc,
+        env.contract.address.as_str(),
+    )?;
+    save_pending_swap(deps.storage, &PendingSwap::Deposit {
+        depositor: depositor.clone(),
+        amount,
+        nusdc_before,
+        minimum_receive,
     })?;
     
     Ok(Response::new()
+        .add_messages(pull_msg)
         .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: config.token_usdc.clone(),
-            msg: to_binary(&transfer_msg)?,
+            msg: to_binary(&approve_msg)?,
             funds: vec![],
         }))
+        .add_submessage(SubMsg::reply_on_success(swap_msg, DEPOSIT_SWAP_REPLY_ID))
+        .add_attribute("action", "deposit")
+        .add_attribute("depositor", depositor)
+        .add_attribute("amount", amount)
+        .add_attribute("minimum_receive", minimum_receive))
+}
+
+// Handle withdraw: burn shares → swap nUSDC → USDC → send to user
+// `amount` is denominated in vault assets (nUSDC)
+fn execute_withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint128,
+    max_slippage_bps: Option<u64>,
+) -> Result<Response, ContractError> {
+    // Withdrawals keep working through partial pauses; only a full halt stops them
+    if PAUSE_STATE.load(deps.storage)?.halted {
+        return Err(ContractError::Paused {});
+    }
+    if amount.is_zero() {
+        return Err(ContractError::ZeroAmount {});
+    }
+    let config = CONFIG.load(deps.storage)?;
+    let slippage_bps = resolve_slippage_bps(&config, max_slippage_bps)?;
+    
+    // A depegged pool trips the circuit breaker, but the withdrawal still goes ahead
+    // so depositors can exit; `minimum_receive` bounds what they get
+    let deviation_bps = probe_peg_deviation_bps(deps.as_ref(), &config, &config.token_nusdc, &config.token_usdc)?;
+    let breaker_event = if deviation_bps > config.max_peg_deviation_bps {
+        Some(trip_circuit_breaker(deps.storage, &config, deviation_bps)?)
+    } else {
+        None
+    };
+    
+    // 1. Burn the shares covering `amount`, rounding against the user
+    let (total_assets, total_shares) = vault_totals(deps.as_ref(), &env, &config)?;
+    let shares = assets_to_shares(amount, total_assets, total_shares, true)?;
+    let user_shares = SHARES.may_load(deps.storage, &info.sender)?.unwrap_or_default();
+    if shares > user_shares {
+        return Err(ContractError::InsufficientPrincipal {
+            requested: amount,
+            available: shares_to_assets(user_shares, total_assets, total_shares, false)?,
+        });
+    }
+    let remaining_shares = user_shares - shares;
+    if remaining_shares.is_zero() {
+        SHARES.remove(deps.storage, &info.sender);
+    } else {
+        SHARES.save(deps.storage, &info.sender, &remaining_shares)?;
+    }
+    TOTAL_SHARES.save(deps.storage, &(total_shares - shares))?;
+    accrue_management_fee(deps.storage, &env)?;
+    let held = query_cw20_balance(deps.as_ref(), &config.token_nusdc, env.contract.address.as_str())?;
+    let index = accrue_yield_index(deps.storage, held)?;
+    checkpoint_user_yield(deps.storage, &info.sender, &index)?;
+    // Release the booked assets pro rata so any loss stays shared by remaining holders
+    TOTAL_ASSETS.update(deps.storage, |val| -> StdResult<_> {
+        Ok(val - val.multiply_ratio(shares, total_shares))
+    })?;
+    
+    // Principal (USDC cost basis) is reduced in proportion to the shares burned
+    let principal = PRINCIPAL_BALANCES
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    let principal_burned = principal.multiply_ratio(shares, user_shares);
+    let remaining = principal - principal_burned;
+    if remaining.is_zero() {
+        PRINCIPAL_BALANCES.remove(deps.storage, &info.sender);
+    } else {
+        PRINCIPAL_BALANCES.save(deps.storage, &info.sender, &remaining)?;
+    }
+    TOTAL_PRINCIPAL.update(deps.storage, |val| -> StdResult<_> {
+        Ok(val.checked_sub(principal_burned)?)
+    })?;
+    
+    // 2. Approve Astroport router to spend nUSDC
+    let approve_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: config.astroport_router.clone(),
+        amount,
+        expires: None,
+    };
+    
+    // 3. Swap nUSDC → USDC via Astroport; the reply forwards the proceeds
+    let (_, minimum_receive) = simulate_swap(
+        deps.as_ref(),
+        &config,
+        &config.token_nusdc,
+        &config.token_usdc,
+        amount,
+        slippage_bps,
+    )?;
+    let swap_msg = build_astroport_swap_msg_nusdc_to_usdc(&config, amount, minimum_receive);
+    let usdc_before = query_cw20_balance(
+        deps.as_ref(),
+        &config.token_usdc,
+        env.contract.address.as_str(),
+    )?;
+    save_pending_swap(deps.storage, &PendingSwap::Withdraw {
+        user: info.sender.clone(),
+        usdc_before,
+        minimum_receive,
+    })?;
+    
+    Ok(Response::new()
         .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: config.token_usdc.clone(),
+            contract_addr: config.token_nusdc.clone(),
             msg: to_binary(&approve_msg)?,
             funds: vec![],
         }))
-        .add_message(swap_msg)
-        .add_attribute("action", "deposit")
-        .add_attribute("amount", amount))
+        .add_submessage(SubMsg::reply_on_success(swap_msg, WITHDRAW_SWAP_REPLY_ID))
+        .add_events(breaker_event)
+        .add_attribute("action", "withdraw")
+        .add_attribute("user", info.sender)
+        .add_attribute("amount", amount)
+        .add_attribute("minimum_receive", minimum_receive)
+        .add_attribute("shares", shares))
 }
 
 // Skim yield and bridge to ICP
@@ -279,41 +1177,108 @@ fn execute_skim(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-) -> StdResult<Response> {
+    max_slippage_bps: Option<u64>,
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), Role::Keeper, &info.sender)?;
     let config = CONFIG.load(deps.storage)?;
-    // Only the yield_collector or ICP manager can call
-    let icp_manager = ICP_MANAGER.load(deps.storage)?;
-    if info.sender != config.yield_collector && info.sender != icp_manager {
-        return Err(StdError::generic_err("Unauthorized"));
+    
+    let recipient = config.yield_collector.to_string();
+    skim_yield(deps, &env, &config, recipient, max_slippage_bps, "skim_yield")
+}
+
+// Shared skim logic: swap nUSDC held above booked assets → USDC and bridge it to ICP
+fn skim_yield(
+    deps: DepsMut,
+    env: &Env,
+    config: &Config,
+    recipient: String,
+    max_slippage_bps: Option<u64>,
+    action: &str,
+) -> Result<Response, ContractError> {
+    let pause = PAUSE_STATE.load(deps.storage)?;
+    if pause.skims || pause.halted {
+        return Err(ContractError::Paused {});
     }
+    let slippage_bps = resolve_slippage_bps(config, max_slippage_bps)?;
     
     // Get nUSDC balance of contract
     let current_balance = query_cw20_balance(
         deps.as_ref(),
         &config.token_nusdc,
-        &env.contract.address.to_string(),
+        env.contract.address.as_str(),
     )?;
-    let total_principal = TOTAL_PRINCIPAL.load(deps.storage)?;
-    if current_balance <= total_principal {
-        return Err(StdError::generic_err("No yield available"));
+    let total_assets = TOTAL_ASSETS.load(deps.storage)?;
+    if current_balance <= total_assets {
+        return Err(ContractError::NoYield {});
+    }
+    let gross_yield = current_balance - total_assets;
+    
+    // Fees come out of the skimmed nUSDC before the rest is swapped and bridged
+    let fee_config = FEE_CONFIG.load(deps.storage)?;
+    let mut fee_state = accrue_management_fee(deps.storage, env)?;
+    let performance_fee = gross_yield.multiply_ratio(fee_config.performance_fee_bps, BPS_DENOMINATOR);
+    let management_fee = fee_state
+        .management_fee_outstanding
+        .min(gross_yield - performance_fee);
+    let yield_amt = gross_yield - performance_fee - management_fee;
+    if yield_amt.is_zero() {
+        // Nothing to swap or bridge, so the skim settles here; the yield still leaves the vault
+        accrue_yield_index(deps.storage, current_balance)?;
+        let (fee_msgs, fee_event) =
+            charge_fees(deps.storage, config, &fee_config, &mut fee_state, performance_fee, management_fee)?;
+        let attribution_event = close_yield_period(deps.storage)?;
+        return Ok(Response::new()
+            .add_messages(fee_msgs)
+            .add_event(fee_event)
+            .add_event(attribution_event)
+            .add_attribute("action", action)
+            .add_attribute("result", "fees_only"));
     }
-    let yield_amt = current_balance - total_principal;
     
     // Approve Astroport router to spend nUSDC
-    let approve_msg = Cw20ExecuteMsg {
-        transfer: None,
-        send: None,
-        approve: Some(ApproveMsg {
-            spender: config.astroport_router.clone(),
-            amount: yield_amt,
-        }),
+    let approve_msg = Cw20ExecuteMsg::IncreaseAllowance {
+        spender: config.astroport_router.clone(),
+        amount: yield_amt,
+        expires: None,
     };
     
+    // A depegged pool trips the circuit breaker instead of selling into it
+    let deviation_bps = probe_peg_deviation_bps(deps.as_ref(), config, &config.token_nusdc, &config.token_usdc)?;
+    if deviation_bps > config.max_peg_deviation_bps {
+        let event = trip_circuit_breaker(deps.storage, config, deviation_bps)?;
+        return Ok(Response::new()
+            .add_event(event)
+            .add_attribute("action", action)
+            .add_attribute("result", "circuit_breaker_tripped"));
+    }
+    
     // Swap yield_amt nUSDC → USDC via Astroport
-    let swap_msg = build_astroport_swap_msg_nusdc_to_usdc(&config, yield_amt);
+    let (_, minimum_receive) = simulate_swap(
+        deps.as_ref(),
+        config,
+        &config.token_nusdc,
+        &config.token_usdc,
+        yield_amt,
+        slippage_bps,
+    )?;
+    let swap_msg = build_astroport_swap_msg_nusdc_to_usdc(config, yield_amt, minimum_receive);
     
-    // Bridge to ICP via Axelar GMP
-    let axelar_msg = build_axelar_gmp_msg(&config, yield_amt, config.yield_collector.to_string());
+    // The reply charges the fees and bridges the USDC actually received to ICP via Axelar GMP
+    let usdc_before = query_cw20_balance(
+        deps.as_ref(),
+        &config.token_usdc,
+        env.contract.address.as_str(),
+    )?;
+    save_pending_swap(deps.storage, &PendingSwap::Skim(PendingSkim {
+        recipient,
+        yield_amount: yield_amt,
+        nusdc_held: current_balance,
+        performance_fee,
+        management_fee,
+        usdc_before,
+        minimum_receive,
+        action: action.to_string(),
+    }))?;
     
     Ok(Response::new()
         .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
@@ -321,29 +1286,438 @@ fn execute_skim(
             msg: to_binary(&approve_msg)?,
             funds: vec![],
         }))
-        .add_message(swap_msg)
-        .add_message(axelar_msg)
-        .add_attribute("action", "skim_yield")
-        .add_attribute("yield_amount", yield_amt))
+        .add_submessage(SubMsg::reply_always(swap_msg, SKIM_SWAP_REPLY_ID))
+        .add_attribute("action", action)
+        .add_attribute("gross_yield", gross_yield)
+        .add_attribute("yield_amount", yield_amt)
+        .add_attribute("minimum_receive", minimum_receive))
 }
 
-// Set ICP manager (only current manager can call)
-fn execute_set_icp_manager(
+// Reply: settle swaps dispatched by deposit, withdraw and skim
+pub fn reply(
     deps: DepsMut,
-    info: MessageInfo,
-    manager: String,
-) -> StdResult<Response> {
-    let current_manager = ICP_MANAGER.load(deps.storage)?;
-    if info.sender != current_manager {
-        return Err(StdError::generic_err("Unauthorized"));
-    }
-    
-    ICP_MANAGER.save(deps.storage, &deps.api.addr_validate(&manager)?)?;
+    env: Env,
+    msg: Reply,
+) -> Result<Response, ContractError> {
+    let pending = PENDING_SWAP
+        .may_load(deps.storage)?
+        .ok_or(ContractError::UnexpectedReply { id: msg.id })?;
+    PENDING_SWAP.remove(deps.storage);
     
-    Ok(Response::new()
-        .add_attribute("action", "set_icp_manager")
-        .add_attribute("manager", manager))
-}
+    match (msg.id, pending) {
+        (
+            DEPOSIT_SWAP_REPLY_ID,
+            PendingSwap::Deposit { depositor, amount, nusdc_before, minimum_receive },
+        ) => settle_deposit(deps, env, depositor, amount, nusdc_before, minimum_receive),
+        (WITHDRAW_SWAP_REPLY_ID, PendingSwap::Withdraw { user, usdc_before, minimum_receive }) => {
+            settle_withdraw(deps, env, user, usdc_before, minimum_receive)
+        },
+        (SKIM_SWAP_REPLY_ID, PendingSwap::Skim(skim)) => {
+            match msg.result {
+                SubMsgResult::Ok(_) => settle_skim(deps, env, skim),
+                SubMsgResult::Err(err) => {
+                    // Swap state was reverted; withdraw the unused router allowance and report.
+                    // No fees are charged and the yield stays in the vault for the next skim.
+                    let config = CONFIG.load(deps.storage)?;
+                    let reset_approve_msg = Cw20ExecuteMsg::DecreaseAllowance {
+                        spender: config.astroport_router.clone(),
+                        amount: skim.yield_amount,
+                        expires: None,
+                    };
+                    Ok(Response::new()
+                        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+                            contract_addr: config.token_nusdc.clone(),
+                            msg: to_binary(&reset_approve_msg)?,
+                            funds: vec![],
+                        }))
+                        .add_attribute("action", "skim_failed")
+                        .add_attribute("yield_amount", skim.yield_amount)
+                        .add_attribute("error", err))
+                },
+            }
+        },
+        (id, _) => Err(ContractError::UnexpectedReply { id }),
+    }
+}
+
+// Settle deposit: mint shares and credit principal for the nUSDC actually received
+fn settle_deposit(
+    deps: DepsMut,
+    env: Env,
+    depositor: Addr,
+    amount: Uint128,
+    nusdc_before: Uint128,
+    minimum_receive: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let nusdc_after = query_cw20_balance(
+        deps.as_ref(),
+        &config.token_nusdc,
+        env.contract.address.as_str(),
+    )?;
+    let received = swap_proceeds(nusdc_before, nusdc_after, minimum_receive)?;
+    
+    // Price shares off the vault as it stood before the swap
+    let booked = TOTAL_ASSETS.load(deps.storage)?;
+    let total_shares = TOTAL_SHARES.load(deps.storage)?;
+    let shares = assets_to_shares(received, booked.min(nusdc_before), total_shares, false)?;
+    if shares.is_zero() {
+        return Err(ContractError::ZeroShares {});
+    }
+    SHARES.update(deps.storage, &depositor, |val| -> StdResult<_> {
+        Ok(val.unwrap_or_default() + shares)
+    })?;
+    TOTAL_SHARES.save(deps.storage, &(total_shares + shares))?;
+    accrue_management_fee(deps.storage, &env)?;
+    let index = accrue_yield_index(deps.storage, nusdc_before)?;
+    checkpoint_user_yield(deps.storage, &depositor, &index)?;
+    TOTAL_ASSETS.save(deps.storage, &(booked + received))?;
+    
+    PRINCIPAL_BALANCES.update(deps.storage, &depositor, |val| -> StdResult<_> {
+        Ok(val.unwrap_or_default() + amount)
+    })?;
+    TOTAL_PRINCIPAL.update(deps.storage, |val| -> StdResult<_> {
+        Ok(val + amount)
+    })?;
+    
+    Ok(Response::new()
+        .add_attribute("action", "settle_deposit")
+        .add_attribute("depositor", depositor)
+        .add_attribute("nusdc_received", received)
+        .add_attribute("shares", shares))
+}
+
+// Settle withdraw: forward the USDC the swap delivered to the user
+fn settle_withdraw(
+    deps: DepsMut,
+    env: Env,
+    user: Addr,
+    usdc_before: Uint128,
+    minimum_receive: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let usdc_after = query_cw20_balance(
+        deps.as_ref(),
+        &config.token_usdc,
+        env.contract.address.as_str(),
+    )?;
+    let received = swap_proceeds(usdc_before, usdc_after, minimum_receive)?;
+    
+    Ok(Response::new()
+        .add_message(build_usdc_transfer_msg(&config, user.to_string(), received)?)
+        .add_attribute("action", "settle_withdraw")
+        .add_attribute("user", user)
+        .add_attribute("usdc_received", received))
+}
+
+// Settle skim: book the skimmed yield, pay the fees and bridge exactly the USDC the
+// swap delivered to ICP
+fn settle_skim(
+    deps: DepsMut,
+    env: Env,
+    skim: PendingSkim,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let usdc_after = query_cw20_balance(
+        deps.as_ref(),
+        &config.token_usdc,
+        env.contract.address.as_str(),
+    )?;
+    let received = swap_proceeds(skim.usdc_before, usdc_after, skim.minimum_receive)?;
+    
+    let index = accrue_yield_index(deps.storage, skim.nusdc_held)?;
+    let shares = skim_attribution(deps.storage, &index)?;
+    let attribution_root = attribution::merkle_root(&shares);
+    let fee_config = FEE_CONFIG.load(deps.storage)?;
+    let mut fee_state = FEE_STATE.load(deps.storage)?;
+    let (fee_msgs, fee_event) = charge_fees(
+        deps.storage,
+        &config,
+        &fee_config,
+        &mut fee_state,
+        skim.performance_fee,
+        skim.management_fee,
+    )?;
+    let mut attribution_event = close_yield_period(deps.storage)?;
+    if let Some(root) = &attribution_root {
+        attribution_event = attribution_event.add_attribute("root", attribution::to_hex(root));
+    }
+    // The leaves, in root order, so depositors can build their proofs
+    attribution_event = attribution_event.add_attributes(
+        shares.iter().map(|share| ("share", format!("{}:{}", share.account, share.amount))),
+    );
+    let axelar_msgs =
+        build_axelar_gmp_msgs(deps.storage, &config, received, skim.recipient, attribution_root)?;
+    
+    Ok(Response::new()
+        .add_messages(fee_msgs)
+        .add_messages(axelar_msgs)
+        .add_event(fee_event)
+        .add_event(attribution_event)
+        .add_attribute("action", format!("settle_{}", skim.action))
+        .add_attribute("usdc_received", received))
+}
+
+// Nominate a new owner (only owner can call); takes effect once accepted
+fn execute_transfer_ownership(
+    deps: DepsMut,
+    info: MessageInfo,
+    new_owner: String,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let new_owner = deps.api.addr_validate(&new_owner)?;
+    PENDING_OWNER.save(deps.storage, &new_owner)?;
+    
+    Ok(Response::new()
+        .add_attribute("action", "transfer_ownership")
+        .add_attribute("pending_owner", new_owner))
+}
+
+// Accept ownership (only the pending owner can call)
+fn execute_accept_ownership(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let pending_owner = PENDING_OWNER.may_load(deps.storage)?;
+    if pending_owner.as_ref() != Some(&info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    OWNER.save(deps.storage, &info.sender)?;
+    PENDING_OWNER.remove(deps.storage);
+    
+    Ok(Response::new()
+        .add_attribute("action", "accept_ownership")
+        .add_attribute("owner", info.sender))
+}
+
+// Grant a role (only owner can call)
+fn execute_grant_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    role: Role,
+    address: String,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let addr = deps.api.addr_validate(&address)?;
+    ROLE_MEMBERS.save(deps.storage, (role.as_str(), &addr), &Empty {})?;
+    
+    Ok(Response::new()
+        .add_attribute("action", "grant_role")
+        .add_attribute("role", role.as_str())
+        .add_attribute("address", addr))
+}
+
+// Revoke a role (only owner can call)
+fn execute_revoke_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    role: Role,
+    address: String,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let addr = deps.api.addr_validate(&address)?;
+    ROLE_MEMBERS.remove(deps.storage, (role.as_str(), &addr));
+    
+    Ok(Response::new()
+        .add_attribute("action", "revoke_role")
+        .add_attribute("role", role.as_str())
+        .add_attribute("address", addr))
+}
+
+// Pause a scope (guardian or owner can call)
+fn execute_pause(
+    deps: DepsMut,
+    info: MessageInfo,
+    scope: PauseScope,
+) -> Result<Response, ContractError> {
+    if assert_owner(deps.as_ref(), &info.sender).is_err() {
+        assert_role(deps.as_ref(), Role::Guardian, &info.sender)?;
+    }
+    let mut pause = PAUSE_STATE.load(deps.storage)?;
+    pause.set(scope, true);
+    PAUSE_STATE.save(deps.storage, &pause)?;
+    
+    Ok(Response::new()
+        .add_event(
+            Event::new("vault_paused")
+                .add_attribute("scope", scope.as_str())
+                .add_attribute("by", info.sender),
+        )
+        .add_attribute("action", "pause"))
+}
+
+// Unpause a scope (only owner can call)
+fn execute_unpause(
+    deps: DepsMut,
+    info: MessageInfo,
+    scope: PauseScope,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let mut pause = PAUSE_STATE.load(deps.storage)?;
+    pause.set(scope, false);
+    PAUSE_STATE.save(deps.storage, &pause)?;
+    
+    Ok(Response::new()
+        .add_event(
+            Event::new("vault_unpaused")
+                .add_attribute("scope", scope.as_str())
+                .add_attribute("by", info.sender),
+        )
+        .add_attribute("action", "unpause"))
+}
+
+// Probe nUSDC → USDC and trip the circuit breaker on a depeg (guardian or keeper can call)
+fn execute_check_peg(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    if assert_role(deps.as_ref(), Role::Guardian, &info.sender).is_err() {
+        assert_role(deps.as_ref(), Role::Keeper, &info.sender)?;
+    }
+    let config = CONFIG.load(deps.storage)?;
+    let deviation_bps = probe_peg_deviation_bps(deps.as_ref(), &config, &config.token_nusdc, &config.token_usdc)?;
+    
+    let mut res = Response::new()
+        .add_attribute("action", "check_peg")
+        .add_attribute("deviation_bps", deviation_bps.to_string());
+    if deviation_bps > config.max_peg_deviation_bps {
+        res = res.add_event(trip_circuit_breaker(deps.storage, &config, deviation_bps)?);
+    }
+    Ok(res)
+}
+
+// Queue a sensitive config change behind the timelock (only owner can call)
+// A new proposal replaces the pending one and restarts the clock
+fn execute_propose_config(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal: SensitiveConfig,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let config = apply_sensitive_config(CONFIG.load(deps.storage)?, proposal.clone());
+    validate_config(deps.api, &config)?;
+    let timelock_seconds = CONFIG_TIMELOCK_SECONDS.load(deps.storage)?;
+    let pending = PendingConfig {
+        config: proposal,
+        proposed_at: env.block.time,
+        active_at: env.block.time.plus_seconds(timelock_seconds),
+    };
+    PENDING_CONFIG.save(deps.storage, &pending)?;
+    
+    Ok(Response::new()
+        .add_event(
+            Event::new("config_proposed")
+                .add_attribute("astroport_router", pending.config.astroport_router)
+                .add_attribute("axelar_gateway", pending.config.axelar_gateway)
+                .add_attribute("token_usdc", pending.config.token_usdc)
+                .add_attribute("token_nusdc", pending.config.token_nusdc)
+                .add_attribute("icp_canister_id", pending.config.icp_canister_id)
+                .add_attribute("icp_chain_name", pending.config.icp_chain_name)
+                .add_attribute("active_at", pending.active_at.seconds().to_string()),
+        )
+        .add_attribute("action", "propose_config"))
+}
+
+// Apply the pending config change once its timelock has passed (only owner can call)
+fn execute_apply_config(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let pending = PENDING_CONFIG
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoPendingConfig {})?;
+    if env.block.time < pending.active_at {
+        return Err(ContractError::ConfigTimelocked {
+            active_at: pending.active_at,
+        });
+    }
+    // Re-validated because other fields may have changed since the proposal
+    let config = apply_sensitive_config(CONFIG.load(deps.storage)?, pending.config);
+    validate_config(deps.api, &config)?;
+    CONFIG.save(deps.storage, &config)?;
+    PENDING_CONFIG.remove(deps.storage);
+    
+    Ok(Response::new()
+        .add_event(Event::new("config_applied").add_attribute("by", info.sender))
+        .add_attribute("action", "apply_config"))
+}
+
+// Change the non-sensitive config fields (owner only); the keeper role follows
+// `yield_collector`
+fn execute_update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    new_config: Config,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let config = CONFIG.load(deps.storage)?;
+    if SensitiveConfig::from(&new_config) != SensitiveConfig::from(&config) {
+        return Err(ContractError::InvalidConfig {
+            reason: "router, gateway, token and ICP fields change via ProposeConfig".to_string(),
+        });
+    }
+    validate_config(deps.api, &new_config)?;
+    if new_config.yield_collector != config.yield_collector {
+        ROLE_MEMBERS.remove(deps.storage, (Role::Keeper.as_str(), &config.yield_collector));
+        ROLE_MEMBERS.save(deps.storage, (Role::Keeper.as_str(), &new_config.yield_collector), &Empty {})?;
+    }
+    CONFIG.save(deps.storage, &new_config)?;
+    
+    Ok(Response::new()
+        .add_event(Event::new("config_updated").add_attribute("by", info.sender))
+        .add_attribute("action", "update_config"))
+}
+
+// Drop the pending config change (guardian or owner can call)
+fn execute_cancel_config(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    if assert_owner(deps.as_ref(), &info.sender).is_err() {
+        assert_role(deps.as_ref(), Role::Guardian, &info.sender)?;
+    }
+    if !PENDING_CONFIG.exists(deps.storage) {
+        return Err(ContractError::NoPendingConfig {});
+    }
+    PENDING_CONFIG.remove(deps.storage);
+    
+    Ok(Response::new()
+        .add_event(Event::new("config_cancelled").add_attribute("by", info.sender))
+        .add_attribute("action", "cancel_config"))
+}
+
+// Update fees and treasury (only owner can call)
+fn execute_set_fee_config(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    treasury: String,
+    performance_fee_bps: u64,
+    management_fee_bps: u64,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let fee_config = FeeConfig {
+        treasury: deps.api.addr_validate(&treasury)?,
+        performance_fee_bps,
+        management_fee_bps,
+    };
+    validate_fee_config(&fee_config)?;
+    // Settle accrual at the old rate before switching
+    accrue_management_fee(deps.storage, &env)?;
+    FEE_CONFIG.save(deps.storage, &fee_config)?;
+    
+    Ok(Response::new()
+        .add_event(
+            Event::new("fee_config_updated")
+                .add_attribute("treasury", fee_config.treasury)
+                .add_attribute("performance_fee_bps", performance_fee_bps.to_string())
+                .add_attribute("management_fee_bps", management_fee_bps.to_string()),
+        )
+        .add_attribute("action", "set_fee_config"))
+}
 
 // Execute actions from ICP
 fn execute_from_icp(
@@ -351,92 +1725,612 @@ fn execute_from_icp(
     env: Env,
     info: MessageInfo,
     action: IcpAction,
-) -> StdResult<Response> {
-    let icp_manager = ICP_MANAGER.load(deps.storage)?;
-    if info.sender != icp_manager {
-        return Err(StdError::generic_err("Unauthorized"));
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), Role::Bridge, &info.sender)?;
+    dispatch_icp_action(deps, env, action)
+}
+
+// Enveloped ICP message relayed by a bridge; each nonce executes once
+fn execute_icp_message(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    envelope: Envelope<IcpToInjective>,
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), Role::Bridge, &info.sender)?;
+    satsuma_messages::check_version(envelope.version).map_err(|err| ContractError::BridgeFailure {
+        reason: err.to_string(),
+    })?;
+    let config = CONFIG.load(deps.storage)?;
+    consume_icp_nonce(deps.storage, &config.icp_chain_name, envelope.nonce)?;
+    
+    let res = dispatch_icp_action(deps, env, envelope.message.into())?;
+    Ok(res.add_attribute("icp_nonce", envelope.nonce.to_string()))
+}
+
+// Helper: Run an authenticated ICP action, unless cross-chain actions are paused
+fn dispatch_icp_action(
+    deps: DepsMut,
+    env: Env,
+    action: IcpAction,
+) -> Result<Response, ContractError> {
+    let pause = PAUSE_STATE.load(deps.storage)?;
+    if pause.cross_chain || pause.halted {
+        return Err(ContractError::Paused {});
     }
     
     match action {
         IcpAction::Deposit { user, amount } => {
+            // Pull from the user's allowance so principal is always backed by USDC
+            let config = CONFIG.load(deps.storage)?;
             let user_addr = deps.api.addr_validate(&user)?;
-            PRINCIPAL_BALANCES.update(deps.storage, &user_addr, |val| {
-                Ok(val.unwrap_or_default() + amount)
-            })?;
-            TOTAL_PRINCIPAL.update(deps.storage, |val| {
-                Ok(val.unwrap_or_default() + amount)
-            })?;
+            let pull_msg = build_usdc_transfer_from_msg(&config, &env, user, amount)?;
             
-            Ok(Response::new()
-                .add_attribute("action", "icp_deposit")
-                .add_attribute("user", user)
-                .add_attribute("amount", amount))
+            let res = deposit_usdc(deps, &env, &config, &user_addr, amount, None, Some(pull_msg))?;
+            Ok(res.add_attribute("origin", "icp"))
         },
-        IcpAction::SkimYield { recipient } => {
-            // Similar to execute_skim but with custom recipient
+        IcpAction::SkimYield { recipient, max_slippage_bps } => {
+            // Same as execute_skim but with custom recipient
             let config = CONFIG.load(deps.storage)?;
-            let current_balance = query_cw20_balance(
-                deps.as_ref(),
-                &config.token_nusdc,
-                &env.contract.address.to_string(),
-            )?;
-            let total_principal = TOTAL_PRINCIPAL.load(deps.storage)?;
-            if current_balance <= total_principal {
-                return Err(StdError::generic_err("No yield available"));
-            }
-            let yield_amt = current_balance - total_principal;
-            
-            let approve_msg = Cw20ExecuteMsg {
-                transfer: None,
-                send: None,
-                approve: Some(ApproveMsg {
-                    spender: config.astroport_router.clone(),
-                    amount: yield_amt,
-                }),
-            };
-            
-            let swap_msg = build_astroport_swap_msg_nusdc_to_usdc(&config, yield_amt);
-            let axelar_msg = build_axelar_gmp_msg(&config, yield_amt, recipient);
-            
-            Ok(Response::new()
-                .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: config.token_nusdc.clone(),
-                    msg: to_binary(&approve_msg)?,
-                    funds: vec![],
-                }))
-                .add_message(swap_msg)
-                .add_message(axelar_msg)
-                .add_attribute("action", "icp_skim_yield")
-                .add_attribute("yield_amount", yield_amt))
-        },
-        IcpAction::UpdateConfig { config: new_config } => {
-            CONFIG.save(deps.storage, &new_config)?;
-            Ok(Response::new()
-                .add_attribute("action", "update_config"))
+            skim_yield(deps, &env, &config, recipient, max_slippage_bps, "icp_skim_yield")
         },
     }
 }
 
+// Inbound Axelar GMP: only calls delivered by the gateway from the configured ICP chain
+// and canister are dispatched
+fn execute_axelar(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    source_chain: String,
+    source_address: String,
+    payload: Binary,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender.as_str() != config.axelar_gateway {
+        return Err(ContractError::Unauthorized {});
+    }
+    if source_chain != config.icp_chain_name || source_address != config.icp_canister_id {
+        return Err(ContractError::BridgeFailure {
+            reason: format!("untrusted GMP source {}:{}", source_chain, source_address),
+        });
+    }
+    let envelope = satsuma_messages::axelar::decode_json_payload::<IcpToInjective>(payload.as_slice())
+        .map_err(|err| ContractError::BridgeFailure {
+            reason: err.to_string(),
+        })?;
+    // Shares the nonce space with ExecuteIcpMessage, so a message cannot run through both
+    consume_icp_nonce(deps.storage, &source_chain, envelope.nonce)?;
+    
+    // The gateway stands in for the bridge role; pause checks are shared with direct ICP calls
+    let res = dispatch_icp_action(deps, env, envelope.message.into())?;
+    Ok(res
+        .add_attribute("source_chain", source_chain)
+        .add_attribute("icp_nonce", envelope.nonce.to_string()))
+}
+
+// Helper: Mark an ICP envelope nonce as executed, failing if it already was
+fn consume_icp_nonce(storage: &mut dyn Storage, source_chain: &str, nonce: u64) -> Result<(), ContractError> {
+    if CONSUMED_NONCES.has(storage, (source_chain, nonce)) {
+        return Err(ContractError::DuplicateMessage {
+            source_chain: source_chain.to_string(),
+            nonce,
+        });
+    }
+    Ok(CONSUMED_NONCES.save(storage, (source_chain, nonce), &Empty {})?)
+}
+
+// Helper: Fail unless `sender` is the owner
+fn assert_owner(deps: Deps, sender: &Addr) -> Result<(), ContractError> {
+    if OWNER.load(deps.storage)? != *sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+// Helper: Fail unless `sender` holds `role`
+fn assert_role(deps: Deps, role: Role, sender: &Addr) -> Result<(), ContractError> {
+    if !ROLE_MEMBERS.has(deps.storage, (role.as_str(), sender)) {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+// Helper: All addresses holding `role`
+fn role_members(deps: Deps, role: Role) -> StdResult<Vec<Addr>> {
+    ROLE_MEMBERS
+        .prefix(role.as_str())
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect()
+}
+
+// Helper: Parse a cw2 version string
+fn parse_version(version: &str) -> Result<Version, ContractError> {
+    version.parse().map_err(|_| ContractError::InvalidMigration {
+        reason: format!("invalid version {}", version),
+    })
+}
+
 // Helper: Query CW20 balance
 fn query_cw20_balance(
     deps: Deps,
     token: &str,
     address: &str,
 ) -> StdResult<Uint128> {
-    let msg = Cw20QueryMsg {
-        balance: Some(BalanceQuery {
-            address: address.to_string(),
-        }),
+    let msg = Cw20QueryMsg::Balance {
+        address: address.to_string(),
     };
-    let res: cw20::BalanceResponse = deps.querier.query(&QueryRequest::WasmSmart {
+    let res: cw20::BalanceResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
         contract_addr: token.to_string(),
         msg: to_binary(&msg)?,
-    })?;
+    }))?;
     Ok(res.balance)
 }
 
+// Helper: Assets backing outstanding shares and the share supply.
+// Assets are the booked nUSDC capped by what the vault holds, so a shortfall is
+// socialised across holders while donations and accrued yield stay skimmable.
+fn vault_totals(deps: Deps, env: &Env, config: &Config) -> StdResult<(Uint128, Uint128)> {
+    let booked = TOTAL_ASSETS.load(deps.storage)?;
+    let held = query_cw20_balance(deps, &config.token_nusdc, env.contract.address.as_str())?;
+    let total_shares = TOTAL_SHARES.load(deps.storage)?;
+    Ok((booked.min(held), total_shares))
+}
+
+// Helper: Convert assets → shares at the current share price
+fn assets_to_shares(
+    assets: Uint128,
+    total_assets: Uint128,
+    total_shares: Uint128,
+    round_up: bool,
+) -> Result<Uint128, ContractError> {
+    if total_shares.is_zero() {
+        return Ok(assets);
+    }
+    if total_assets.is_zero() {
+        return Err(ContractError::NoAssets {});
+    }
+    Ok(mul_div(assets, total_shares, total_assets, round_up)?)
+}
+
+// Helper: Convert shares → assets at the current share price
+fn shares_to_assets(
+    shares: Uint128,
+    total_assets: Uint128,
+    total_shares: Uint128,
+    round_up: bool,
+) -> StdResult<Uint128> {
+    if total_shares.is_zero() {
+        return Ok(shares);
+    }
+    mul_div(shares, total_assets, total_shares, round_up)
+}
+
+// Helper: amount * numerator / denominator in 256-bit precision
+fn mul_div(
+    amount: Uint128,
+    numerator: Uint128,
+    denominator: Uint128,
+    round_up: bool,
+) -> StdResult<Uint128> {
+    let product = amount.full_mul(numerator);
+    let denominator = Uint256::from(denominator);
+    let mut result = product / denominator;
+    if round_up && !(product % denominator).is_zero() {
+        result += Uint256::one();
+    }
+    Ok(Uint128::try_from(result)?)
+}
+
+// Helper: Record the swap in flight; only one may be pending at a time
+fn save_pending_swap(
+    storage: &mut dyn Storage,
+    pending: &PendingSwap,
+) -> Result<(), ContractError> {
+    if PENDING_SWAP.may_load(storage)?.is_some() {
+        return Err(ContractError::SwapPending {});
+    }
+    Ok(PENDING_SWAP.save(storage, pending)?)
+}
+
+// Helper: Balance delta produced by a settled swap, checked against minimum_receive
+fn swap_proceeds(
+    before: Uint128,
+    after: Uint128,
+    minimum_receive: Uint128,
+) -> Result<Uint128, ContractError> {
+    let received = after.saturating_sub(before);
+    if received < minimum_receive {
+        return Err(ContractError::SlippageExceeded { minimum: minimum_receive, received });
+    }
+    Ok(received)
+}
+
+// Helper: Build CW20 Transfer sending USDC from the vault to `recipient`
+fn build_usdc_transfer_msg(
+    config: &Config,
+    recipient: String,
+    amount: Uint128,
+) -> StdResult<CosmosMsg> {
+    let transfer_msg = Cw20ExecuteMsg::Transfer { recipient, amount };
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: config.token_usdc.clone(),
+        msg: to_binary(&transfer_msg)?,
+        funds: vec![],
+    }))
+}
+
+// Helper: Build CW20 TransferFrom pulling USDC from `owner` into the vault
+fn build_usdc_transfer_from_msg(
+    config: &Config,
+    env: &Env,
+    owner: String,
+    amount: Uint128,
+) -> StdResult<CosmosMsg> {
+    let transfer_from_msg = Cw20ExecuteMsg::TransferFrom {
+        owner,
+        recipient: env.contract.address.to_string(),
+        amount,
+    };
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: config.token_usdc.clone(),
+        msg: to_binary(&transfer_from_msg)?,
+        funds: vec![],
+    }))
+}
+
+// Helper: Validate every config field; contract addresses must be valid bech32 for this chain
+fn validate_config(api: &dyn Api, config: &Config) -> Result<(), ContractError> {
+    for (field, addr) in [
+        ("token_usdc", config.token_usdc.as_str()),
+        ("token_nusdc", config.token_nusdc.as_str()),
+        ("astroport_router", config.astroport_router.as_str()),
+        ("axelar_gateway", config.axelar_gateway.as_str()),
+        ("yield_collector", config.yield_collector.as_str()),
+    ] {
+        api.addr_validate(addr).map_err(|err| ContractError::InvalidConfig {
+            reason: format!("{}: {}", field, err),
+        })?;
+    }
+    if config.token_usdc == config.token_nusdc {
+        return Err(ContractError::InvalidConfig {
+            reason: "token_usdc and token_nusdc must differ".to_string(),
+        });
+    }
+    if config.icp_canister_id.trim().is_empty() || config.icp_chain_name.trim().is_empty() {
+        return Err(ContractError::InvalidConfig {
+            reason: "icp_canister_id and icp_chain_name must be set".to_string(),
+        });
+    }
+    if let Some(fee) = &config.axelar_gas_fee {
+        if fee.denom.trim().is_empty() || fee.amount.is_zero() {
+            return Err(ContractError::InvalidConfig {
+                reason: "axelar_gas_fee must be a non-zero native coin".to_string(),
+            });
+        }
+    }
+    validate_slippage_bps(config.max_slippage_bps)?;
+    validate_peg_deviation_bps(config.max_peg_deviation_bps)?;
+    Ok(())
+}
+
+// Helper: `config` with the sensitive fields replaced by `sensitive`
+fn apply_sensitive_config(config: Config, sensitive: SensitiveConfig) -> Config {
+    Config {
+        token_usdc: sensitive.token_usdc,
+        token_nusdc: sensitive.token_nusdc,
+        astroport_router: sensitive.astroport_router,
+        axelar_gateway: sensitive.axelar_gateway,
+        icp_canister_id: sensitive.icp_canister_id,
+        icp_chain_name: sensitive.icp_chain_name,
+        ..config
+    }
+}
+
+// Helper: Reject fees above their caps
+fn validate_fee_config(fee_config: &FeeConfig) -> Result<(), ContractError> {
+    if fee_config.performance_fee_bps > MAX_PERFORMANCE_FEE_BPS {
+        return Err(ContractError::InvalidConfig {
+            reason: format!(
+                "performance fee {} bps exceeds limit of {} bps",
+                fee_config.performance_fee_bps, MAX_PERFORMANCE_FEE_BPS
+            ),
+        });
+    }
+    if fee_config.management_fee_bps > MAX_MANAGEMENT_FEE_BPS {
+        return Err(ContractError::InvalidConfig {
+            reason: format!(
+                "management fee {} bps exceeds limit of {} bps",
+                fee_config.management_fee_bps, MAX_MANAGEMENT_FEE_BPS
+            ),
+        });
+    }
+    Ok(())
+}
+
+// Helper: Management fee on `booked` assets between the last accrual and `now`
+fn management_fee_accrued(
+    fee_config: &FeeConfig,
+    fee_state: &FeeState,
+    booked: Uint128,
+    now: Timestamp,
+) -> StdResult<Uint128> {
+    let elapsed = now.seconds().saturating_sub(fee_state.last_accrued_at.seconds());
+    mul_div(
+        booked,
+        Uint128::from(fee_config.management_fee_bps) * Uint128::from(elapsed),
+        Uint128::from(BPS_DENOMINATOR) * Uint128::from(SECONDS_PER_YEAR),
+        false,
+    )
+}
+
+// Helper: Accrue the management fee up to this block; call before booked assets change
+fn accrue_management_fee(storage: &mut dyn Storage, env: &Env) -> StdResult<FeeState> {
+    let fee_config = FEE_CONFIG.load(storage)?;
+    let mut fee_state = FEE_STATE.load(storage)?;
+    let booked = TOTAL_ASSETS.load(storage)?;
+    fee_state.management_fee_outstanding += management_fee_accrued(&fee_config, &fee_state, booked, env.block.time)?;
+    fee_state.last_accrued_at = env.block.time;
+    FEE_STATE.save(storage, &fee_state)?;
+    Ok(fee_state)
+}
+
+// Helper: Record fees taken from a skim and build the nUSDC transfer to the treasury
+fn charge_fees(
+    storage: &mut dyn Storage,
+    config: &Config,
+    fee_config: &FeeConfig,
+    fee_state: &mut FeeState,
+    performance_fee: Uint128,
+    management_fee: Uint128,
+) -> StdResult<(Vec<CosmosMsg>, Event)> {
+    fee_state.management_fee_outstanding -= management_fee;
+    FEE_STATE.save(storage, fee_state)?;
+    
+    let total = performance_fee + management_fee;
+    let mut msgs = vec![];
+    if !total.is_zero() {
+        let transfer_msg = Cw20ExecuteMsg::Transfer {
+            recipient: fee_config.treasury.to_string(),
+            amount: total,
+        };
+        msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: config.token_nusdc.clone(),
+            msg: to_binary(&transfer_msg)?,
+            funds: vec![],
+        }));
+    }
+    let event = Event::new("vault_fees")
+        .add_attribute("treasury", fee_config.treasury.to_string())
+        .add_attribute("performance_fee", performance_fee)
+        .add_attribute("management_fee", management_fee)
+        .add_attribute("management_fee_outstanding", fee_state.management_fee_outstanding);
+    Ok((msgs, event))
+}
+
+// Helper: The index after folding in yield that appeared since the last accrual
+// `held` is the vault's nUSDC balance; yield accrued while no principal is deposited stays unattributed
+fn next_yield_index(
+    index: &YieldIndex,
+    held: Uint128,
+    booked: Uint128,
+    total_principal: Uint128,
+) -> YieldIndex {
+    let pending = held.saturating_sub(booked);
+    let mut next = index.clone();
+    if pending > index.indexed_yield && !total_principal.is_zero() {
+        let new_yield = pending - index.indexed_yield;
+        next.yield_per_principal += Uint256::from(new_yield) * Uint256::from(YIELD_INDEX_SCALE)
+            / Uint256::from(total_principal);
+    }
+    next.indexed_yield = pending;
+    next
+}
+
+// Helper: Accrue the yield index; call before principal or booked assets change
+fn accrue_yield_index(storage: &mut dyn Storage, held: Uint128) -> StdResult<YieldIndex> {
+    let index = next_yield_index(
+        &YIELD_INDEX.load(storage)?,
+        held,
+        TOTAL_ASSETS.load(storage)?,
+        TOTAL_PRINCIPAL.load(storage)?,
+    );
+    YIELD_INDEX.save(storage, &index)?;
+    Ok(index)
+}
+
+// Helper: Yield earned by `principal` while the index moved from `from` to `to`
+fn yield_between(principal: Uint128, from: Uint256, to: Uint256) -> StdResult<Uint128> {
+    Ok(Uint128::try_from(
+        Uint256::from(principal) * (to - from) / Uint256::from(YIELD_INDEX_SCALE),
+    )?)
+}
+
+// Helper: `user` brought up to `index`, plus its yield taken by skims since its last
+// checkpoint. Principal is constant between checkpoints, so only the last skim matters.
+fn settle_user_yield(user: &UserYield, principal: Uint128, index: &YieldIndex) -> StdResult<(UserYield, Uint128)> {
+    let mut unskimmed = user.unskimmed;
+    let mut skimmed = Uint128::zero();
+    let mut from = user.index;
+    if user.skims < index.skims {
+        skimmed = unskimmed + yield_between(principal, from, index.skimmed_per_principal)?;
+        unskimmed = Uint128::zero();
+        from = index.skimmed_per_principal;
+    }
+    unskimmed += yield_between(principal, from, index.yield_per_principal)?;
+    let settled = UserYield {
+        index: index.yield_per_principal,
+        unskimmed,
+        skims: index.skims,
+    };
+    Ok((settled, skimmed))
+}
+
+// Helper: Bring `account`'s yield up to `index`; call before its principal changes
+fn checkpoint_user_yield(storage: &mut dyn Storage, account: &Addr, index: &YieldIndex) -> StdResult<()> {
+    let principal = PRINCIPAL_BALANCES.may_load(storage, account)?.unwrap_or_default();
+    let user = USER_YIELD.may_load(storage, account)?.unwrap_or_default();
+    let (user, skimmed) = settle_user_yield(&user, principal, index)?;
+    if !skimmed.is_zero() {
+        SKIMMED_YIELD.update(storage, account, |val| -> StdResult<_> {
+            Ok(val.unwrap_or_default() + skimmed)
+        })?;
+    }
+    USER_YIELD.save(storage, account, &user)
+}
+
+// Helper: Each account's share of the skim closing at `index`, settled in memory and
+// ordered by account, as the leaves of its attribution root. Reads one entry per
+// depositor; accounts that have withdrawn everything may still hold unskimmed yield.
+fn skim_attribution(storage: &dyn Storage, index: &YieldIndex) -> StdResult<Vec<YieldShare>> {
+    let mut accounts = USER_YIELD
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<BTreeMap<_, _>>>()?;
+    for account in PRINCIPAL_BALANCES.keys(storage, None, None, Order::Ascending) {
+        accounts.entry(account?).or_default();
+    }
+    
+    let mut shares = vec![];
+    for (account, user) in accounts {
+        let principal = PRINCIPAL_BALANCES.may_load(storage, &account)?.unwrap_or_default();
+        let (user, _) = settle_user_yield(&user, principal, index)?;
+        if !user.unskimmed.is_zero() {
+            shares.push(YieldShare {
+                account: account.to_string(),
+                amount: user.unskimmed.u128(),
+            });
+        }
+    }
+    Ok(shares)
+}
+
+// Helper: Close the attribution period on a skim. Yield accrued up to the current index
+// is skimmed; each account books its share at its next checkpoint, so this is O(1).
+fn close_yield_period(storage: &mut dyn Storage) -> StdResult<Event> {
+    let mut index = YIELD_INDEX.load(storage)?;
+    index.skims += 1;
+    index.skimmed_per_principal = index.yield_per_principal;
+    index.indexed_yield = Uint128::zero();
+    YIELD_INDEX.save(storage, &index)?;
+    
+    Ok(Event::new("yield_attribution")
+        .add_attribute("skim", index.skims.to_string())
+        .add_attribute("yield_per_principal", index.yield_per_principal.to_string()))
+}
+
+// Helper: Reject slippage tolerances beyond the hard limit
+fn validate_slippage_bps(bps: u64) -> Result<(), ContractError> {
+    if bps > MAX_SLIPPAGE_BPS_LIMIT {
+        return Err(ContractError::InvalidConfig {
+            reason: format!(
+                "max slippage {} bps exceeds limit of {} bps",
+                bps, MAX_SLIPPAGE_BPS_LIMIT
+            ),
+        });
+    }
+    Ok(())
+}
+
+// Helper: Reject circuit breaker bounds outside 0..=100%
+fn validate_peg_deviation_bps(bps: u64) -> Result<(), ContractError> {
+    if bps > BPS_DENOMINATOR {
+        return Err(ContractError::InvalidConfig {
+            reason: format!("max peg deviation {} bps exceeds 100%", bps),
+        });
+    }
+    Ok(())
+}
+
+// Helper: Peg deviation of a fixed PEG_PROBE_AMOUNT quote from `offer_token` to `ask_token`.
+// The probe keeps trade-size price impact and fee rounding out of the peg signal;
+// `minimum_receive` bounds those per trade.
+fn probe_peg_deviation_bps(
+    deps: Deps,
+    config: &Config,
+    offer_token: &str,
+    ask_token: &str,
+) -> Result<u64, ContractError> {
+    let probe = Uint128::new(PEG_PROBE_AMOUNT);
+    let (quote, _) = simulate_swap(deps, config, offer_token, ask_token, probe, 0)?;
+    Ok(peg_deviation_bps(probe, quote))
+}
+
+// Helper: How far `quote` is from a 1:1 swap of `amount`, in basis points
+fn peg_deviation_bps(amount: Uint128, quote: Uint128) -> u64 {
+    let diff = if quote > amount { quote - amount } else { amount - quote };
+    let bps = diff.multiply_ratio(BPS_DENOMINATOR, amount);
+    u64::try_from(bps.u128()).unwrap_or(u64::MAX)
+}
+
+// Helper: Pause deposits, skims and cross-chain actions after a depeg.
+// Withdrawals stay open so depositors can still exit.
+fn trip_circuit_breaker(
+    storage: &mut dyn Storage,
+    config: &Config,
+    deviation_bps: u64,
+) -> Result<Event, ContractError> {
+    let mut pause = PAUSE_STATE.load(storage)?;
+    pause.deposits = true;
+    pause.skims = true;
+    pause.cross_chain = true;
+    PAUSE_STATE.save(storage, &pause)?;
+    
+    Ok(Event::new("circuit_breaker_tripped")
+        .add_attribute("deviation_bps", deviation_bps.to_string())
+        .add_attribute("max_peg_deviation_bps", config.max_peg_deviation_bps.to_string()))
+}
+
+// Helper: Per-call slippage override, falling back to the configured default
+fn resolve_slippage_bps(
+    config: &Config,
+    max_slippage_bps: Option<u64>,
+) -> Result<u64, ContractError> {
+    let bps = max_slippage_bps.unwrap_or(config.max_slippage_bps);
+    validate_slippage_bps(bps)?;
+    Ok(bps)
+}
+
+// Helper: Simulate a swap on the Astroport router.
+// Returns the quoted amount and the minimum_receive allowed by `slippage_bps`.
+fn simulate_swap(
+    deps: Deps,
+    config: &Config,
+    offer_token: &str,
+    ask_token: &str,
+    amount: Uint128,
+    slippage_bps: u64,
+) -> Result<(Uint128, Uint128), ContractError> {
+    let msg = AstroportRouterQueryMsg::SimulateSwapOperations {
+        offer_amount: amount,
+        operations: vec![SwapOperation::AstroSwap {
+            offer_asset_info: AssetInfo::Token {
+                contract_addr: offer_token.to_string(),
+            },
+            ask_asset_info: AssetInfo::Token {
+                contract_addr: ask_token.to_string(),
+            },
+        }],
+    };
+    let res: SimulateSwapOperationsResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: config.astroport_router.clone(),
+        msg: to_binary(&msg)?,
+    }))?;
+    if res.amount.is_zero() {
+        return Err(ContractError::InvalidQuote {});
+    }
+    let minimum_receive = res
+        .amount
+        .multiply_ratio(BPS_DENOMINATOR - slippage_bps, BPS_DENOMINATOR);
+    Ok((res.amount, minimum_receive))
+}
+
 // Helper: Build Astroport swap message (USDC → nUSDC)
-fn build_astroport_swap_msg(config: &Config, amount: Uint128) -> CosmosMsg {
+fn build_astroport_swap_msg(
+    config: &Config,
+    amount: Uint128,
+    minimum_receive: Uint128,
+) -> CosmosMsg {
     let swap_msg = AstroportSwapMsg {
         swap: AstroportSwap {
             offer_asset: OfferAsset {
@@ -448,7 +2342,8 @@ fn build_astroport_swap_msg(config: &Config, amount: Uint128) -> CosmosMsg {
             ask_asset_info: AssetInfo::Token {
                 contract_addr: config.token_nusdc.clone(),
             },
-            minimum_receive: None,
+            minimum_receive: Some(minimum_receive),
+            to: None,
         },
     };
     
@@ -460,7 +2355,11 @@ fn build_astroport_swap_msg(config: &Config, amount: Uint128) -> CosmosMsg {
 }
 
 // Helper: Build Astroport swap message (nUSDC → USDC)
-fn build_astroport_swap_msg_nusdc_to_usdc(config: &Config, amount: Uint128) -> CosmosMsg {
+fn build_astroport_swap_msg_nusdc_to_usdc(
+    config: &Config,
+    amount: Uint128,
+    minimum_receive: Uint128,
+) -> CosmosMsg {
     let swap_msg = AstroportSwapMsg {
         swap: AstroportSwap {
             offer_asset: OfferAsset {
@@ -472,7 +2371,8 @@ fn build_astroport_swap_msg_nusdc_to_usdc(config: &Config, amount: Uint128) -> C
             ask_asset_info: AssetInfo::Token {
                 contract_addr: config.token_usdc.clone(),
             },
-            minimum_receive: None,
+            minimum_receive: Some(minimum_receive),
+            to: None,
         },
     };
     
@@ -483,22 +2383,681 @@ fn build_astroport_swap_msg_nusdc_to_usdc(config: &Config, amount: Uint128) -> C
     })
 }
 
-// Helper: Build Axelar GMP message
-fn build_axelar_gmp_msg(config: &Config, amount: Uint128, recipient_principal: String) -> CosmosMsg {
-    let payload = IcpPayload {
-        principal: recipient_principal,
-        amount,
-        action: "deposit_yield".to_string(),
-    };
-    CosmosMsg::Wasm(WasmMsg::Execute {
+// Helper: Build the Axelar GMP call bridging `amount` USDC to ICP. The gateway pulls the
+// USDC through an allowance granted just before the call, and the configured gas fee
+// is attached as funds
+fn build_axelar_gmp_msgs(
+    storage: &mut dyn Storage,
+    config: &Config,
+    amount: Uint128,
+    recipient_principal: String,
+    attribution_root: Option<attribution::Hash>,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    if amount.is_zero() {
+        return Err(ContractError::BridgeFailure {
+            reason: "nothing to bridge".to_string(),
+        });
+    }
+    if config.icp_canister_id.is_empty() {
+        return Err(ContractError::BridgeFailure {
+            reason: "ICP canister id is not configured".to_string(),
+        });
+    }
+    let nonce = OUTBOUND_NONCE.may_load(storage)?.unwrap_or_default() + 1;
+    OUTBOUND_NONCE.save(storage, &nonce)?;
+    let envelope = Envelope::new(
+        nonce,
+        InjectiveToIcp::DepositYield {
+            principal: recipient_principal,
+            amount: amount.u128(),
+            attribution_root,
+        },
+    );
+    let payload = satsuma_messages::axelar::encode_gmp_payload(&envelope, config.gmp_encoding);
+    let approve_msg = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: config.token_usdc.clone(),
+        msg: to_binary(&Cw20ExecuteMsg::IncreaseAllowance {
+            spender: config.axelar_gateway.clone(),
+            amount,
+            expires: None,
+        })?,
+        funds: vec![],
+    });
+    let gmp_msg = CosmosMsg::Wasm(WasmMsg::Execute {
         contract_addr: config.axelar_gateway.clone(),
         msg: to_binary(&AxelarGmpMsg {
-            destination_chain: "icp".to_string(),
+            destination_chain: config.icp_chain_name.clone(),
             destination_address: config.icp_canister_id.clone(),
-            payload: to_binary(&payload).unwrap(),
+            payload: Binary::from(payload),
             symbol: "USDC".to_string(),
             amount,
-        }).unwrap(),
-        funds: vec![],
-    })
-} 
\ No newline at end of file
+        })?,
+        funds: config.axelar_gas_fee.clone().into_iter().collect(),
+    });
+    Ok(vec![approve_msg, gmp_msg])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR,
+    };
+    use cosmwasm_std::{ContractResult, OwnedDeps, SubMsgResponse, SystemResult};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    
+    type TestDeps = OwnedDeps<MockStorage, MockApi, MockQuerier>;
+    
+    // Token balances and the router's rate, shared with the mocked querier
+    struct Chain {
+        balances: HashMap<(String, String), u128>,
+        /// Router quote for either direction, in basis points of the offer
+        rate_bps: u64,
+    }
+    
+    impl Chain {
+        fn balance(&self, token: &str, holder: &str) -> u128 {
+            self.balances.get(&(token.to_string(), holder.to_string())).copied().unwrap_or_default()
+        }
+        
+        fn set_balance(&mut self, token: &str, holder: &str, amount: u128) {
+            self.balances.insert((token.to_string(), holder.to_string()), amount);
+        }
+        
+        fn quote(&self, amount: u128) -> u128 {
+            amount * self.rate_bps as u128 / BPS_DENOMINATOR as u128
+        }
+        
+        // Settle a swap the vault dispatched, at the router's current rate
+        fn swap(&mut self, offer: &str, ask: &str, amount: u128) {
+            let quote = self.quote(amount);
+            let offered = self.balance(offer, MOCK_CONTRACT_ADDR).saturating_sub(amount);
+            let asked = self.balance(ask, MOCK_CONTRACT_ADDR) + quote;
+            self.set_balance(offer, MOCK_CONTRACT_ADDR, offered);
+            self.set_balance(ask, MOCK_CONTRACT_ADDR, asked);
+        }
+    }
+    
+    fn test_instantiate_msg() -> InstantiateMsg {
+        InstantiateMsg {
+            token_usdc: "usdc".to_string(),
+            token_nusdc: "nusdc".to_string(),
+            astroport_router: "router".to_string(),
+            axelar_gateway: "gateway".to_string(),
+            icp_canister_id: "canister".to_string(),
+            yield_collector: "collector".to_string(),
+            pool_id_usdc_nusdc: 1,
+            pool_id_nusdc_usdc: 2,
+            icp_manager: "bridge".to_string(),
+            max_slippage_bps: None,
+            max_peg_deviation_bps: None,
+            icp_chain_name: None,
+            gmp_encoding: None,
+            axelar_gas_fee: None,
+            config_timelock_seconds: None,
+            treasury: Some("treasury".to_string()),
+            performance_fee_bps: None,
+            management_fee_bps: None,
+            owner: None,
+        }
+    }
+    
+    fn setup_with(msg: InstantiateMsg) -> (TestDeps, Rc<RefCell<Chain>>) {
+        let chain = Rc::new(RefCell::new(Chain {
+            balances: HashMap::new(),
+            rate_bps: BPS_DENOMINATOR,
+        }));
+        let mut deps = mock_dependencies();
+        let querier_chain = chain.clone();
+        deps.querier.update_wasm(move |query| {
+            let chain = querier_chain.borrow();
+            let res = match query {
+                WasmQuery::Smart { contract_addr, msg } if contract_addr == "router" => {
+                    let AstroportRouterQueryMsg::SimulateSwapOperations { offer_amount, .. } = from_binary(msg).unwrap();
+                    to_binary(&SimulateSwapOperationsResponse {
+                        amount: Uint128::new(chain.quote(offer_amount.u128())),
+                    })
+                },
+                WasmQuery::Smart { contract_addr, msg } => match from_binary(msg).unwrap() {
+                    Cw20QueryMsg::Balance { address } => to_binary(&cw20::BalanceResponse {
+                        balance: Uint128::new(chain.balance(contract_addr, &address)),
+                    }),
+                    other => panic!("unexpected cw20 query {:?}", other),
+                },
+                other => panic!("unexpected wasm query {:?}", other),
+            };
+            SystemResult::Ok(ContractResult::Ok(res.unwrap()))
+        });
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        (deps, chain)
+    }
+    
+    fn setup() -> (TestDeps, Rc<RefCell<Chain>>) {
+        setup_with(test_instantiate_msg())
+    }
+    
+    fn reply_ok(id: u64) -> Reply {
+        Reply {
+            id,
+            result: SubMsgResult::Ok(SubMsgResponse { events: vec![], data: None }),
+        }
+    }
+    
+    // Helper: Deposit `amount` USDC and settle the swap
+    fn deposit(deps: &mut TestDeps, chain: &Rc<RefCell<Chain>>, user: &str, amount: u128) -> Response {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(user, &[]),
+            ExecuteMsg::Deposit { amount: Uint128::new(amount), max_slippage_bps: None },
+        )
+        .unwrap();
+        chain.borrow_mut().swap("usdc", "nusdc", amount);
+        reply(deps.as_mut(), mock_env(), reply_ok(DEPOSIT_SWAP_REPLY_ID)).unwrap()
+    }
+    
+    // Helper: Withdraw `amount` nUSDC worth of shares and settle the swap
+    fn withdraw(deps: &mut TestDeps, chain: &Rc<RefCell<Chain>>, user: &str, amount: u128) -> Response {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(user, &[]),
+            ExecuteMsg::Withdraw { amount: Uint128::new(amount), max_slippage_bps: None },
+        )
+        .unwrap();
+        chain.borrow_mut().swap("nusdc", "usdc", amount);
+        reply(deps.as_mut(), mock_env(), reply_ok(WITHDRAW_SWAP_REPLY_ID)).unwrap()
+    }
+    
+    fn query_uint(deps: &TestDeps, msg: QueryMsg) -> Uint128 {
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+    }
+    
+    fn test_config() -> Config {
+        Config {
+            token_usdc: "usdc".to_string(),
+            token_nusdc: "nusdc".to_string(),
+            astroport_router: "router".to_string(),
+            axelar_gateway: "gateway".to_string(),
+            icp_canister_id: "canister".to_string(),
+            yield_collector: Addr::unchecked("collector"),
+            pool_id_usdc_nusdc: 1,
+            pool_id_nusdc_usdc: 2,
+            max_slippage_bps: DEFAULT_MAX_SLIPPAGE_BPS,
+            max_peg_deviation_bps: DEFAULT_MAX_PEG_DEVIATION_BPS,
+            icp_chain_name: DEFAULT_ICP_CHAIN_NAME.to_string(),
+            gmp_encoding: GmpEncoding::default(),
+            axelar_gas_fee: None,
+        }
+    }
+    
+    // Helper: Contract and JSON body of a `WasmMsg::Execute`
+    fn wasm_execute(msg: &CosmosMsg) -> (String, String) {
+        match msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, .. }) => {
+                (contract_addr.clone(), String::from_utf8(msg.to_vec()).unwrap())
+            },
+            other => panic!("expected a wasm execute, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn cw20_messages_are_externally_tagged() {
+        let config = test_config();
+        let env = mock_env();
+        
+        let transfer = build_usdc_transfer_msg(&config, "alice".to_string(), Uint128::new(5)).unwrap();
+        assert_eq!(
+            wasm_execute(&transfer),
+            ("usdc".to_string(), r#"{"transfer":{"recipient":"alice","amount":"5"}}"#.to_string()),
+        );
+        
+        let transfer_from = build_usdc_transfer_from_msg(&config, &env, "alice".to_string(), Uint128::new(7)).unwrap();
+        assert_eq!(
+            wasm_execute(&transfer_from).1,
+            format!(
+                r#"{{"transfer_from":{{"owner":"alice","recipient":"{}","amount":"7"}}}}"#,
+                env.contract.address
+            ),
+        );
+        
+        let allowance = Cw20ExecuteMsg::IncreaseAllowance {
+            spender: config.astroport_router.clone(),
+            amount: Uint128::new(9),
+            expires: None,
+        };
+        assert_eq!(
+            to_binary(&allowance).unwrap().to_vec(),
+            br#"{"increase_allowance":{"spender":"router","amount":"9","expires":null}}"#.to_vec(),
+        );
+        
+        let balance = Cw20QueryMsg::Balance { address: "vault".to_string() };
+        assert_eq!(to_binary(&balance).unwrap().to_vec(), br#"{"balance":{"address":"vault"}}"#.to_vec());
+    }
+    
+    #[test]
+    fn shares_are_priced_off_assets_the_vault_holds() {
+        let (mut deps, chain) = setup();
+        deposit(&mut deps, &chain, "alice", 1_000_000);
+        assert_eq!(query_uint(&deps, QueryMsg::Shares { address: "alice".to_string() }), Uint128::new(1_000_000));
+        assert_eq!(query_uint(&deps, QueryMsg::TotalAssets {}), Uint128::new(1_000_000));
+        
+        // Yield held above booked assets is skimmable, not share value
+        chain.borrow_mut().set_balance("nusdc", MOCK_CONTRACT_ADDR, 1_100_000);
+        assert_eq!(query_uint(&deps, QueryMsg::ConvertToAssets { shares: Uint128::new(1_000) }), Uint128::new(1_000));
+        
+        // A shortfall is shared by every holder
+        chain.borrow_mut().set_balance("nusdc", MOCK_CONTRACT_ADDR, 500_000);
+        assert_eq!(
+            query_uint(&deps, QueryMsg::ConvertToAssets { shares: Uint128::new(1_000_000) }),
+            Uint128::new(500_000),
+        );
+        assert_eq!(query_uint(&deps, QueryMsg::ConvertToShares { assets: Uint128::new(1_001) }), Uint128::new(2_002));
+        assert_eq!(query_uint(&deps, QueryMsg::PreviewWithdraw { assets: Uint128::new(1) }), Uint128::new(2));
+        
+        // A later depositor buys in at the reduced price
+        deposit(&mut deps, &chain, "bob", 500_000);
+        assert_eq!(query_uint(&deps, QueryMsg::Shares { address: "bob".to_string() }), Uint128::new(1_000_000));
+        assert_eq!(query_uint(&deps, QueryMsg::TotalShares {}), Uint128::new(2_000_000));
+        assert_eq!(query_uint(&deps, QueryMsg::TotalPrincipal {}), Uint128::new(1_500_000));
+    }
+    
+    #[test]
+    fn withdraw_burns_shares_and_principal_pro_rata() {
+        let (mut deps, chain) = setup();
+        deposit(&mut deps, &chain, "alice", 1_000_000);
+        withdraw(&mut deps, &chain, "alice", 400_000);
+        
+        assert_eq!(query_uint(&deps, QueryMsg::Shares { address: "alice".to_string() }), Uint128::new(600_000));
+        assert_eq!(query_uint(&deps, QueryMsg::Principal { address: "alice".to_string() }), Uint128::new(600_000));
+        assert_eq!(query_uint(&deps, QueryMsg::TotalAssets {}), Uint128::new(600_000));
+        
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            ExecuteMsg::Withdraw { amount: Uint128::new(600_001), max_slippage_bps: None },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InsufficientPrincipal {
+            requested: Uint128::new(600_001),
+            available: Uint128::new(600_000),
+        });
+        
+        withdraw(&mut deps, &chain, "alice", 600_000);
+        assert_eq!(query_uint(&deps, QueryMsg::TotalShares {}), Uint128::zero());
+        assert_eq!(query_uint(&deps, QueryMsg::Principal { address: "alice".to_string() }), Uint128::zero());
+    }
+    
+    #[test]
+    fn deposit_reply_credits_what_the_swap_delivered() {
+        let (mut deps, chain) = setup();
+        chain.borrow_mut().rate_bps = 9_980;
+        let res = deposit(&mut deps, &chain, "alice", 1_000_000);
+        
+        assert!(res.attributes.iter().any(|attr| attr.key == "nusdc_received" && attr.value == "998000"));
+        assert_eq!(query_uint(&deps, QueryMsg::Shares { address: "alice".to_string() }), Uint128::new(998_000));
+        assert_eq!(query_uint(&deps, QueryMsg::TotalAssets {}), Uint128::new(998_000));
+        // Principal stays the USDC cost basis
+        assert_eq!(query_uint(&deps, QueryMsg::Principal { address: "alice".to_string() }), Uint128::new(1_000_000));
+        assert!(PENDING_SWAP.may_load(deps.as_ref().storage).unwrap().is_none());
+    }
+    
+    #[test]
+    fn deposit_reply_enforces_minimum_receive() {
+        let (mut deps, chain) = setup();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            ExecuteMsg::Deposit { amount: Uint128::new(1_000_000), max_slippage_bps: Some(100) },
+        )
+        .unwrap();
+        // The pool moved between simulation and execution
+        chain.borrow_mut().rate_bps = 9_800;
+        chain.borrow_mut().swap("usdc", "nusdc", 1_000_000);
+        
+        let err = reply(deps.as_mut(), mock_env(), reply_ok(DEPOSIT_SWAP_REPLY_ID)).unwrap_err();
+        assert_eq!(err, ContractError::SlippageExceeded {
+            minimum: Uint128::new(990_000),
+            received: Uint128::new(980_000),
+        });
+    }
+    
+    #[test]
+    fn withdraw_reply_forwards_the_proceeds() {
+        let (mut deps, chain) = setup();
+        deposit(&mut deps, &chain, "alice", 1_000_000);
+        chain.borrow_mut().rate_bps = 9_990;
+        let res = withdraw(&mut deps, &chain, "alice", 500_000);
+        
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            wasm_execute(&res.messages[0].msg),
+            ("usdc".to_string(), r#"{"transfer":{"recipient":"alice","amount":"499500"}}"#.to_string()),
+        );
+    }
+    
+    #[test]
+    fn reply_without_a_pending_swap_is_rejected() {
+        let (mut deps, chain) = setup();
+        deposit(&mut deps, &chain, "alice", 1_000_000);
+        
+        let err = reply(deps.as_mut(), mock_env(), reply_ok(DEPOSIT_SWAP_REPLY_ID)).unwrap_err();
+        assert_eq!(err, ContractError::UnexpectedReply { id: DEPOSIT_SWAP_REPLY_ID });
+    }
+    
+    #[test]
+    fn depeg_rejects_deposits_but_lets_withdrawals_exit() {
+        let (mut deps, chain) = setup();
+        deposit(&mut deps, &chain, "alice", 1_000_000);
+        chain.borrow_mut().rate_bps = 9_500;
+        
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            ExecuteMsg::Deposit { amount: Uint128::new(1_000_000), max_slippage_bps: None },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::PegDeviation { deviation_bps: 500, max_bps: DEFAULT_MAX_PEG_DEVIATION_BPS });
+        
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            ExecuteMsg::Withdraw { amount: Uint128::new(400_000), max_slippage_bps: None },
+        )
+        .unwrap();
+        assert!(res.events.iter().any(|event| event.ty == "circuit_breaker_tripped"));
+        assert_eq!(res.messages.len(), 2);
+        let pause = PAUSE_STATE.load(deps.as_ref().storage).unwrap();
+        assert!(pause.deposits && pause.skims && pause.cross_chain && !pause.halted);
+        
+        // Exits keep working while the breaker is tripped
+        chain.borrow_mut().swap("nusdc", "usdc", 400_000);
+        let res = reply(deps.as_mut(), mock_env(), reply_ok(WITHDRAW_SWAP_REPLY_ID)).unwrap();
+        assert_eq!(
+            wasm_execute(&res.messages[0].msg).1,
+            r#"{"transfer":{"recipient":"alice","amount":"380000"}}"#,
+        );
+        withdraw(&mut deps, &chain, "alice", 600_000);
+        assert_eq!(query_uint(&deps, QueryMsg::TotalShares {}), Uint128::zero());
+    }
+    
+    #[test]
+    fn sensitive_config_changes_wait_out_the_timelock() {
+        let (mut deps, _) = setup();
+        let mut proposal = SensitiveConfig::from(&CONFIG.load(deps.as_ref().storage).unwrap());
+        proposal.axelar_gateway = "gateway2".to_string();
+        
+        // UpdateConfig cannot touch the sensitive fields
+        let mut update = CONFIG.load(deps.as_ref().storage).unwrap();
+        update.axelar_gateway = "gateway2".to_string();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::UpdateConfig { config: update })
+            .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidConfig { .. }));
+        
+        let env = mock_env();
+        execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), ExecuteMsg::ProposeConfig { config: proposal })
+            .unwrap();
+        let active_at = env.block.time.plus_seconds(DEFAULT_CONFIG_TIMELOCK_SECONDS);
+        
+        let mut early = env.clone();
+        early.block.time = active_at.minus_seconds(1);
+        let err = execute(deps.as_mut(), early, mock_info("owner", &[]), ExecuteMsg::ApplyConfig {}).unwrap_err();
+        assert_eq!(err, ContractError::ConfigTimelocked { active_at });
+        assert_eq!(CONFIG.load(deps.as_ref().storage).unwrap().axelar_gateway, "gateway");
+        
+        let mut ready = env;
+        ready.block.time = active_at;
+        execute(deps.as_mut(), ready, mock_info("owner", &[]), ExecuteMsg::ApplyConfig {}).unwrap();
+        assert_eq!(CONFIG.load(deps.as_ref().storage).unwrap().axelar_gateway, "gateway2");
+        assert!(PENDING_CONFIG.may_load(deps.as_ref().storage).unwrap().is_none());
+    }
+    
+    #[test]
+    fn skim_bridges_the_usdc_with_gas_through_the_gateway() {
+        let mut msg = test_instantiate_msg();
+        msg.axelar_gas_fee = Some(Coin::new(5_000, "inj"));
+        let (mut deps, chain) = setup_with(msg);
+        deposit(&mut deps, &chain, "alice", 1_000_000);
+        chain.borrow_mut().set_balance("nusdc", MOCK_CONTRACT_ADDR, 1_100_000);
+        
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("collector", &[]),
+            ExecuteMsg::SkimYield { max_slippage_bps: None },
+        )
+        .unwrap();
+        chain.borrow_mut().swap("nusdc", "usdc", 100_000);
+        let res = reply(deps.as_mut(), mock_env(), reply_ok(SKIM_SWAP_REPLY_ID)).unwrap();
+        
+        // The gateway pulls the USDC it bridges and is paid gas in the same call
+        assert_eq!(res.messages.len(), 2);
+        assert_eq!(
+            wasm_execute(&res.messages[0].msg),
+            (
+                "usdc".to_string(),
+                r#"{"increase_allowance":{"spender":"gateway","amount":"100000","expires":null}}"#.to_string(),
+            ),
+        );
+        match &res.messages[1].msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, funds }) => {
+                assert_eq!(contract_addr, "gateway");
+                assert_eq!(funds, &vec![Coin::new(5_000, "inj")]);
+                let gmp: AxelarGmpMsg = from_binary(msg).unwrap();
+                assert_eq!(gmp.destination_address, "canister");
+                assert_eq!(gmp.symbol, "USDC");
+                assert_eq!(gmp.amount, Uint128::new(100_000));
+            },
+            other => panic!("expected the gateway call, got {:?}", other),
+        }
+    }
+    
+    // Helper: Vault with alice's 1_000_000 deposit, 100_000 nUSDC of yield and a year of
+    // management fee outstanding; returns the env a skim should run in
+    fn setup_skim_with_fees() -> (TestDeps, Rc<RefCell<Chain>>, Env) {
+        let mut msg = test_instantiate_msg();
+        msg.performance_fee_bps = Some(1_000);
+        msg.management_fee_bps = Some(100);
+        let (mut deps, chain) = setup_with(msg);
+        deposit(&mut deps, &chain, "alice", 1_000_000);
+        chain.borrow_mut().set_balance("nusdc", MOCK_CONTRACT_ADDR, 1_100_000);
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(SECONDS_PER_YEAR);
+        (deps, chain, env)
+    }
+    
+    fn skim(deps: &mut TestDeps, env: Env) -> Response {
+        execute(
+            deps.as_mut(),
+            env,
+            mock_info("collector", &[]),
+            ExecuteMsg::SkimYield { max_slippage_bps: None },
+        )
+        .unwrap()
+    }
+    
+    #[test]
+    fn skim_fees_are_paid_once_the_swap_settles() {
+        let (mut deps, chain, env) = setup_skim_with_fees();
+        let res = skim(&mut deps, env.clone());
+        
+        // 10% performance fee and 1% of booked assets for the year come out of the yield
+        assert!(res.attributes.iter().any(|attr| attr.key == "yield_amount" && attr.value == "80000"));
+        assert_eq!(res.messages.len(), 2);
+        assert!(res.events.is_empty());
+        assert_eq!(YIELD_INDEX.load(deps.as_ref().storage).unwrap(), YieldIndex::default());
+        
+        chain.borrow_mut().swap("nusdc", "usdc", 80_000);
+        let res = reply(deps.as_mut(), env.clone(), reply_ok(SKIM_SWAP_REPLY_ID)).unwrap();
+        assert_eq!(
+            wasm_execute(&res.messages[0].msg),
+            ("nusdc".to_string(), r#"{"transfer":{"recipient":"treasury","amount":"20000"}}"#.to_string()),
+        );
+        assert_eq!(res.messages.len(), 3);
+        assert!(res.events.iter().any(|event| event.ty == "vault_fees"));
+        assert_eq!(FEE_STATE.load(deps.as_ref().storage).unwrap().management_fee_outstanding, Uint128::zero());
+        
+        // The whole gross yield is attributed to alice as skimmed
+        chain.borrow_mut().set_balance("nusdc", MOCK_CONTRACT_ADDR, 1_000_000);
+        let user: UserYieldResponse =
+            from_binary(&query(deps.as_ref(), env, QueryMsg::UserYield { address: "alice".to_string() }).unwrap())
+                .unwrap();
+        assert_eq!(user, UserYieldResponse {
+            unskimmed: Uint128::zero(),
+            skimmed: Uint128::new(100_000),
+            lifetime: Uint128::new(100_000),
+        });
+    }
+    
+    #[test]
+    fn failed_skim_swap_charges_no_fees_and_keeps_the_yield() {
+        let (mut deps, chain, env) = setup_skim_with_fees();
+        skim(&mut deps, env.clone());
+        
+        let failed = Reply {
+            id: SKIM_SWAP_REPLY_ID,
+            result: SubMsgResult::Err("pool drained".to_string()),
+        };
+        let res = reply(deps.as_mut(), env.clone(), failed).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            wasm_execute(&res.messages[0].msg),
+            (
+                "nusdc".to_string(),
+                r#"{"decrease_allowance":{"spender":"router","amount":"80000","expires":null}}"#.to_string(),
+            ),
+        );
+        assert_eq!(
+            FEE_STATE.load(deps.as_ref().storage).unwrap().management_fee_outstanding,
+            Uint128::new(10_000),
+        );
+        assert_eq!(YIELD_INDEX.load(deps.as_ref().storage).unwrap(), YieldIndex::default());
+        assert!(PENDING_SWAP.may_load(deps.as_ref().storage).unwrap().is_none());
+        
+        // The same yield and fees are taken by the next skim
+        let res = skim(&mut deps, env.clone());
+        assert!(res.attributes.iter().any(|attr| attr.key == "yield_amount" && attr.value == "80000"));
+        chain.borrow_mut().swap("nusdc", "usdc", 80_000);
+        let res = reply(deps.as_mut(), env, reply_ok(SKIM_SWAP_REPLY_ID)).unwrap();
+        assert_eq!(
+            wasm_execute(&res.messages[0].msg).1,
+            r#"{"transfer":{"recipient":"treasury","amount":"20000"}}"#,
+        );
+        assert_eq!(YIELD_INDEX.load(deps.as_ref().storage).unwrap().skims, 1);
+    }
+    
+    #[test]
+    fn depeg_trips_the_breaker_before_a_skim_books_anything() {
+        let (mut deps, chain, env) = setup_skim_with_fees();
+        chain.borrow_mut().rate_bps = 9_500;
+        let res = skim(&mut deps, env);
+        
+        assert!(res.messages.is_empty());
+        assert!(res.attributes.iter().any(|attr| attr.key == "result" && attr.value == "circuit_breaker_tripped"));
+        assert!(PAUSE_STATE.load(deps.as_ref().storage).unwrap().skims);
+        assert_eq!(YIELD_INDEX.load(deps.as_ref().storage).unwrap(), YieldIndex::default());
+        assert_eq!(
+            FEE_STATE.load(deps.as_ref().storage).unwrap().management_fee_outstanding,
+            Uint128::new(10_000),
+        );
+    }
+    
+    // Helper: Decode the outbound envelope of a skim settlement's gateway call
+    fn bridged_envelope(res: &Response) -> Envelope<InjectiveToIcp> {
+        let gateway_call = res.messages.iter().rev().find(|sub| wasm_execute(&sub.msg).0 == "gateway").unwrap();
+        let CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) = &gateway_call.msg else { unreachable!() };
+        let gmp: AxelarGmpMsg = from_binary(msg).unwrap();
+        satsuma_messages::axelar::decode_json_payload(gmp.payload.as_slice()).unwrap()
+    }
+    
+    #[test]
+    fn icp_skims_commit_to_the_depositors_shares_on_chain() {
+        let (mut deps, chain) = setup();
+        deposit(&mut deps, &chain, "alice", 1_000_000);
+        deposit(&mut deps, &chain, "bob", 3_000_000);
+        chain.borrow_mut().set_balance("nusdc", MOCK_CONTRACT_ADDR, 4_400_000);
+        // Carol joins after the yield accrued and has no share of it
+        deposit(&mut deps, &chain, "carol", 1_000_000);
+        
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bridge", &[]),
+            ExecuteMsg::ExecuteFromIcp {
+                action: IcpAction::SkimYield { recipient: "icp-user".to_string(), max_slippage_bps: None },
+            },
+        )
+        .unwrap();
+        chain.borrow_mut().swap("nusdc", "usdc", 400_000);
+        let res = reply(deps.as_mut(), mock_env(), reply_ok(SKIM_SWAP_REPLY_ID)).unwrap();
+        
+        let shares = vec![
+            YieldShare { account: "alice".to_string(), amount: 100_000 },
+            YieldShare { account: "bob".to_string(), amount: 300_000 },
+        ];
+        let root = attribution::merkle_root(&shares).unwrap();
+        let InjectiveToIcp::DepositYield { principal, amount, attribution_root } = bridged_envelope(&res).message;
+        assert_eq!((principal.as_str(), amount), ("icp-user", 400_000));
+        assert_eq!(attribution_root, Some(root));
+        
+        let event = res.events.iter().find(|event| event.ty == "yield_attribution").unwrap();
+        let attrs: Vec<_> = event.attributes.iter().map(|attr| (attr.key.as_str(), attr.value.as_str())).collect();
+        assert!(attrs.contains(&("root", attribution::to_hex(&root).as_str())));
+        assert_eq!(
+            attrs.iter().filter(|(key, _)| *key == "share").map(|(_, value)| *value).collect::<Vec<_>>(),
+            vec!["alice:100000", "bob:300000"],
+        );
+    }
+    
+    #[test]
+    fn depositors_from_before_the_yield_index_settle_lazily() {
+        let (mut deps, chain) = setup();
+        deposit(&mut deps, &chain, "alice", 1_000_000);
+        deposit(&mut deps, &chain, "bob", 1_000_000);
+        // 1.4 state: principal booked but no yield checkpoints
+        USER_YIELD.remove(deps.as_mut().storage, &Addr::unchecked("alice"));
+        USER_YIELD.remove(deps.as_mut().storage, &Addr::unchecked("bob"));
+        migrate_v1_4_to_v1_5(deps.as_mut().storage).unwrap();
+        assert!(USER_YIELD.may_load(deps.as_ref().storage, &Addr::unchecked("alice")).unwrap().is_none());
+        
+        chain.borrow_mut().set_balance("nusdc", MOCK_CONTRACT_ADDR, 2_200_000);
+        let user: UserYieldResponse = from_binary(
+            &query(deps.as_ref(), mock_env(), QueryMsg::UserYield { address: "alice".to_string() }).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(user.unskimmed, Uint128::new(100_000));
+        
+        // Bob checkpoints by withdrawing half, then a skim takes the yield of both
+        withdraw(&mut deps, &chain, "bob", 500_000);
+        assert_eq!(
+            USER_YIELD.load(deps.as_ref().storage, &Addr::unchecked("bob")).unwrap().unskimmed,
+            Uint128::new(100_000),
+        );
+        skim(&mut deps, mock_env());
+        chain.borrow_mut().swap("nusdc", "usdc", 200_000);
+        let res = reply(deps.as_mut(), mock_env(), reply_ok(SKIM_SWAP_REPLY_ID)).unwrap();
+        let InjectiveToIcp::DepositYield { attribution_root, .. } = bridged_envelope(&res).message;
+        assert_eq!(
+            attribution_root,
+            attribution::merkle_root(&[
+                YieldShare { account: "alice".to_string(), amount: 100_000 },
+                YieldShare { account: "bob".to_string(), amount: 100_000 },
+            ]),
+        );
+        
+        // Alice books her share at her next checkpoint
+        chain.borrow_mut().set_balance("nusdc", MOCK_CONTRACT_ADDR, 1_500_000);
+        deposit(&mut deps, &chain, "alice", 1_000);
+        assert_eq!(SKIMMED_YIELD.load(deps.as_ref().storage, &Addr::unchecked("alice")).unwrap(), Uint128::new(100_000));
+        assert_eq!(
+            USER_YIELD.load(deps.as_ref().storage, &Addr::unchecked("alice")).unwrap().unskimmed,
+            Uint128::zero(),
+        );
+    }
+}
\ No newline at end of file
@@ -0,0 +1,163 @@
+// Per-depositor yield attribution for a skim, committed to by a Merkle root
+// Leaves are sha256(0x00 || account || amount as 16 big-endian bytes). Inner nodes are
+// sha256(0x01 || min(a, b) || max(a, b)), so proofs need no left/right flags. An odd
+// node at the end of a level is promoted unchanged.
+
+use crate::WireError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct YieldShare {
+    // Injective depositor address
+    pub account: String,
+    #[serde(with = "crate::amount")]
+    pub amount: u128,
+}
+
+pub fn leaf_hash(share: &YieldShare) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(share.account.as_bytes());
+    hasher.update(share.amount.to_be_bytes());
+    hasher.finalize().into()
+}
+
+// None when there is nothing to attribute
+pub fn merkle_root(shares: &[YieldShare]) -> Option<Hash> {
+    let mut level: Vec<Hash> = shares.iter().map(leaf_hash).collect();
+    if level.is_empty() {
+        return None;
+    }
+    while level.len() > 1 {
+        level = level.chunks(2).map(|pair| match pair {
+            [a, b] => node_hash(a, b),
+            [a] => *a,
+            _ => unreachable!(),
+        }).collect();
+    }
+    Some(level[0])
+}
+
+// Sibling hashes from the leaf of `shares[index]` up to the root
+pub fn merkle_proof(shares: &[YieldShare], index: usize) -> Option<Vec<Hash>> {
+    if index >= shares.len() {
+        return None;
+    }
+    let mut level: Vec<Hash> = shares.iter().map(leaf_hash).collect();
+    let mut index = index;
+    let mut proof = Vec::new();
+    while level.len() > 1 {
+        if let Some(sibling) = level.get(index ^ 1) {
+            proof.push(*sibling);
+        }
+        level = level.chunks(2).map(|pair| match pair {
+            [a, b] => node_hash(a, b),
+            [a] => *a,
+            _ => unreachable!(),
+        }).collect();
+        index /= 2;
+    }
+    Some(proof)
+}
+
+pub fn verify_proof(root: &Hash, share: &YieldShare, proof: &[Hash]) -> bool {
+    let computed = proof.iter().fold(leaf_hash(share), |acc, sibling| node_hash(&acc, sibling));
+    computed == *root
+}
+
+fn node_hash(a: &Hash, b: &Hash) -> Hash {
+    let (low, high) = if a <= b { (a, b) } else { (b, a) };
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(low);
+    hasher.update(high);
+    hasher.finalize().into()
+}
+
+pub fn to_hex(hash: &Hash) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(value: &str) -> Result<Hash, WireError> {
+    let malformed = || WireError::Malformed(format!("invalid hash {}", value));
+    if value.len() != 64 || !value.is_ascii() {
+        return Err(malformed());
+    }
+    let mut hash = [0u8; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).map_err(|_| malformed())?;
+    }
+    Ok(hash)
+}
+
+// Optional roots travel as lowercase hex strings
+pub(crate) mod hex_root {
+    use super::{from_hex, to_hex, Hash};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<Hash>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(hash) => serializer.serialize_some(&to_hex(hash)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Hash>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(value) => from_hex(&value).map(Some).map_err(serde::de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shares(count: usize) -> Vec<YieldShare> {
+        (0..count)
+            .map(|i| YieldShare {
+                account: format!("inj1user{}", i),
+                amount: 1_000 * (i as u128 + 1),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_share_proves_against_the_root() {
+        for count in 1..=9 {
+            let shares = shares(count);
+            let root = merkle_root(&shares).unwrap();
+            for (i, share) in shares.iter().enumerate() {
+                let proof = merkle_proof(&shares, i).unwrap();
+                assert!(verify_proof(&root, share, &proof), "share {} of {}", i, count);
+            }
+        }
+    }
+
+    #[test]
+    fn altered_share_fails_verification() {
+        let shares = shares(5);
+        let root = merkle_root(&shares).unwrap();
+        let proof = merkle_proof(&shares, 2).unwrap();
+        let forged = YieldShare {
+            amount: shares[2].amount + 1,
+            ..shares[2].clone()
+        };
+        assert!(!verify_proof(&root, &forged, &proof));
+        assert!(merkle_root(&[]).is_none());
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        let root = merkle_root(&shares(3)).unwrap();
+        assert_eq!(from_hex(&to_hex(&root)).unwrap(), root);
+        assert!(from_hex("abc").is_err());
+    }
+}
@@ -0,0 +1,284 @@
+// Axelar GMP payload encodings
+// Axelar prefixes GMP payloads with a 4-byte version:
+//   0x00000001: abi.encode(string method, string[] argNames, string[] argTypes, bytes argValues)
+//   0x00000002: JSON
+// Only `InjectiveToIcp` has an ABI form; ICP -> Injective messages are always JSON.
+
+use crate::{check_version, decode, encode, Envelope, InjectiveToIcp, WireError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub const PAYLOAD_VERSION_ABI: [u8; 4] = [0, 0, 0, 1];
+pub const PAYLOAD_VERSION_JSON: [u8; 4] = [0, 0, 0, 2];
+
+const DEPOSIT_YIELD_METHOD: &str = "deposit_yield";
+// A fifth `attribution_root` argument is appended when the skim carries one
+const DEPOSIT_YIELD_ARG_NAMES: [&str; 5] = ["version", "nonce", "principal", "amount", "attribution_root"];
+const DEPOSIT_YIELD_ARG_TYPES: [&str; 5] = ["uint16", "uint64", "string", "uint256", "bytes32"];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GmpEncoding {
+    #[default]
+    Json,
+    Abi,
+}
+
+pub fn encode_gmp_payload(envelope: &Envelope<InjectiveToIcp>, encoding: GmpEncoding) -> Vec<u8> {
+    match encoding {
+        GmpEncoding::Json => [&PAYLOAD_VERSION_JSON[..], &encode(envelope)].concat(),
+        GmpEncoding::Abi => [&PAYLOAD_VERSION_ABI[..], &encode_abi(envelope)].concat(),
+    }
+}
+
+pub fn decode_gmp_payload(bytes: &[u8]) -> Result<Envelope<InjectiveToIcp>, WireError> {
+    match split_version(bytes) {
+        Some((PAYLOAD_VERSION_JSON, body)) => decode(body),
+        Some((PAYLOAD_VERSION_ABI, body)) => decode_abi(body),
+        _ => Err(WireError::Malformed("unknown GMP payload version".to_string())),
+    }
+}
+
+// JSON payload with or without the Axelar version prefix
+pub fn decode_json_payload<T: DeserializeOwned>(bytes: &[u8]) -> Result<Envelope<T>, WireError> {
+    match split_version(bytes) {
+        Some((PAYLOAD_VERSION_JSON, body)) => decode(body),
+        _ => decode(bytes),
+    }
+}
+
+fn split_version(bytes: &[u8]) -> Option<([u8; 4], &[u8])> {
+    if bytes.len() < 4 {
+        return None;
+    }
+    let (version, body) = bytes.split_at(4);
+    Some((version.try_into().ok()?, body))
+}
+
+enum Token {
+    Uint(u128),
+    Word([u8; 32]),
+    Bytes(Vec<u8>),
+    StringArray(Vec<String>),
+}
+
+fn encode_abi(envelope: &Envelope<InjectiveToIcp>) -> Vec<u8> {
+    let InjectiveToIcp::DepositYield { principal, amount, attribution_root } = &envelope.message;
+    let mut values = vec![
+        Token::Uint(envelope.version as u128),
+        Token::Uint(envelope.nonce as u128),
+        Token::Bytes(principal.as_bytes().to_vec()),
+        Token::Uint(*amount),
+    ];
+    if let Some(root) = attribution_root {
+        values.push(Token::Word(*root));
+    }
+    let arg_count = values.len();
+    encode_tokens(&[
+        Token::Bytes(DEPOSIT_YIELD_METHOD.as_bytes().to_vec()),
+        Token::StringArray(DEPOSIT_YIELD_ARG_NAMES[..arg_count].iter().map(|s| s.to_string()).collect()),
+        Token::StringArray(DEPOSIT_YIELD_ARG_TYPES[..arg_count].iter().map(|s| s.to_string()).collect()),
+        Token::Bytes(encode_tokens(&values)),
+    ])
+}
+
+fn decode_abi(body: &[u8]) -> Result<Envelope<InjectiveToIcp>, WireError> {
+    let method = read_string(body, 0)?;
+    if method != DEPOSIT_YIELD_METHOD {
+        return Err(WireError::Malformed(format!("unexpected method {}", method)));
+    }
+    let arg_names = read_string_array(body, 32)?;
+    let arg_types = read_string_array(body, 64)?;
+    let arg_count = arg_names.len();
+    if !(4..=5).contains(&arg_count)
+        || arg_names != DEPOSIT_YIELD_ARG_NAMES[..arg_count]
+        || arg_types != DEPOSIT_YIELD_ARG_TYPES[..arg_count]
+    {
+        return Err(WireError::Malformed("unexpected deposit_yield arguments".to_string()));
+    }
+    let values = read_bytes(body, 96)?;
+
+    let version = u16::try_from(read_uint(&values, 0)?)
+        .map_err(|_| WireError::Malformed("version out of range".to_string()))?;
+    check_version(version)?;
+    let nonce = u64::try_from(read_uint(&values, 32)?)
+        .map_err(|_| WireError::Malformed("nonce out of range".to_string()))?;
+    let principal = read_string(&values, 64)?;
+    let amount = read_uint(&values, 96)?;
+    let attribution_root = match arg_count {
+        5 => Some(read_word(&values, 128)?),
+        _ => None,
+    };
+    Ok(Envelope {
+        version,
+        nonce,
+        message: InjectiveToIcp::DepositYield { principal, amount, attribution_root },
+    })
+}
+
+// Helper: ABI-encode a tuple; static values go in the head, dynamic ones behind offsets
+fn encode_tokens(tokens: &[Token]) -> Vec<u8> {
+    let mut head = Vec::new();
+    let mut tail = Vec::new();
+    let head_len = tokens.len() * 32;
+    for token in tokens {
+        match token {
+            Token::Uint(value) => head.extend(word(*value)),
+            Token::Word(value) => head.extend(value),
+            Token::Bytes(data) => {
+                head.extend(word((head_len + tail.len()) as u128));
+                tail.extend(encode_bytes(data));
+            },
+            Token::StringArray(items) => {
+                head.extend(word((head_len + tail.len()) as u128));
+                let elements: Vec<Token> = items.iter().map(|item| Token::Bytes(item.as_bytes().to_vec())).collect();
+                tail.extend(word(items.len() as u128));
+                tail.extend(encode_tokens(&elements));
+            },
+        }
+    }
+    head.extend(tail);
+    head
+}
+
+fn encode_bytes(data: &[u8]) -> Vec<u8> {
+    let mut out = word(data.len() as u128).to_vec();
+    out.extend(data);
+    out.resize(32 + data.len().div_ceil(32) * 32, 0);
+    out
+}
+
+fn word(value: u128) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[16..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn read_word(data: &[u8], at: usize) -> Result<[u8; 32], WireError> {
+    let word = data
+        .get(at..checked_end(at, 32)?)
+        .ok_or_else(|| WireError::Malformed("ABI data too short".to_string()))?;
+    Ok(word.try_into().expect("32 bytes"))
+}
+
+// Helper: `start + len` for offsets and lengths read from untrusted data
+fn checked_end(start: usize, len: usize) -> Result<usize, WireError> {
+    start
+        .checked_add(len)
+        .ok_or_else(|| WireError::Malformed("ABI offset out of range".to_string()))
+}
+
+fn read_uint(data: &[u8], at: usize) -> Result<u128, WireError> {
+    let word = read_word(data, at)?;
+    if word[..16].iter().any(|b| *b != 0) {
+        return Err(WireError::Malformed("ABI integer exceeds u128".to_string()));
+    }
+    Ok(u128::from_be_bytes(word[16..].try_into().expect("16 bytes")))
+}
+
+fn read_offset(data: &[u8], at: usize) -> Result<usize, WireError> {
+    usize::try_from(read_uint(data, at)?).map_err(|_| WireError::Malformed("ABI offset out of range".to_string()))
+}
+
+// Helper: dynamic `bytes` / `string` whose offset is stored at `head`
+fn read_bytes(data: &[u8], head: usize) -> Result<Vec<u8>, WireError> {
+    let start = read_offset(data, head)?;
+    let len = read_offset(data, start)?;
+    let data_start = checked_end(start, 32)?;
+    data.get(data_start..checked_end(data_start, len)?)
+        .map(|bytes| bytes.to_vec())
+        .ok_or_else(|| WireError::Malformed("ABI data too short".to_string()))
+}
+
+fn read_string(data: &[u8], head: usize) -> Result<String, WireError> {
+    String::from_utf8(read_bytes(data, head)?).map_err(|_| WireError::Malformed("ABI string is not utf-8".to_string()))
+}
+
+fn read_string_array(data: &[u8], head: usize) -> Result<Vec<String>, WireError> {
+    let start = read_offset(data, head)?;
+    let len = read_offset(data, start)?;
+    let elements = data
+        .get(checked_end(start, 32)?..)
+        .ok_or_else(|| WireError::Malformed("ABI data too short".to_string()))?;
+    (0..len).map(|i| read_string(elements, i * 32)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deposit_yield() -> Envelope<InjectiveToIcp> {
+        Envelope::new(
+            42,
+            InjectiveToIcp::DepositYield {
+                principal: "rrkah-fqaaa-aaaaa-aaaaq-cai".to_string(),
+                amount: 123_456_789,
+                attribution_root: None,
+            },
+        )
+    }
+
+    #[test]
+    fn json_payload_round_trips() {
+        let payload = encode_gmp_payload(&deposit_yield(), GmpEncoding::Json);
+        assert_eq!(payload[..4], PAYLOAD_VERSION_JSON);
+        assert_eq!(decode_gmp_payload(&payload).unwrap(), deposit_yield());
+    }
+
+    #[test]
+    fn abi_payload_round_trips() {
+        let payload = encode_gmp_payload(&deposit_yield(), GmpEncoding::Abi);
+        assert_eq!(payload[..4], PAYLOAD_VERSION_ABI);
+        // Four dynamic arguments: the head is four offsets
+        assert_eq!(read_uint(&payload[4..], 0).unwrap(), 128);
+        assert_eq!(decode_gmp_payload(&payload).unwrap(), deposit_yield());
+    }
+
+    #[test]
+    fn abi_payload_carries_attribution_root() {
+        let mut envelope = deposit_yield();
+        let InjectiveToIcp::DepositYield { attribution_root, .. } = &mut envelope.message;
+        *attribution_root = Some([7; 32]);
+        let payload = encode_gmp_payload(&envelope, GmpEncoding::Abi);
+        assert_eq!(decode_gmp_payload(&payload).unwrap(), envelope);
+    }
+
+    #[test]
+    fn abi_strings_are_padded() {
+        assert_eq!(encode_bytes(b"abc").len(), 64);
+        assert_eq!(encode_bytes(&[0u8; 32]).len(), 64);
+        assert_eq!(encode_bytes(&[]).len(), 32);
+    }
+
+    #[test]
+    fn json_payload_prefix_is_optional_inbound() {
+        let envelope = Envelope::new(
+            1,
+            crate::IcpToInjective::SkimYield {
+                recipient: "2vxsx-fae".to_string(),
+                max_slippage_bps: Some(25),
+            },
+        );
+        let raw = encode(&envelope);
+        let prefixed = [&PAYLOAD_VERSION_JSON[..], &raw].concat();
+        assert_eq!(decode_json_payload::<crate::IcpToInjective>(&raw).unwrap(), envelope);
+        assert_eq!(decode_json_payload::<crate::IcpToInjective>(&prefixed).unwrap(), envelope);
+    }
+
+    #[test]
+    fn rejects_unknown_prefix_and_truncated_abi() {
+        assert!(decode_gmp_payload(&[0, 0, 0, 9, b'{']).is_err());
+        let payload = encode_gmp_payload(&deposit_yield(), GmpEncoding::Abi);
+        assert!(decode_gmp_payload(&payload[..payload.len() - 32]).is_err());
+    }
+
+    #[test]
+    fn oversized_abi_offsets_are_malformed() {
+        let huge = usize::MAX as u128;
+        let length_overflows = [word(32), word(huge)].concat();
+        assert!(matches!(read_bytes(&length_overflows, 0), Err(WireError::Malformed(_))));
+        let offset_overflows = word(huge - 16);
+        assert!(matches!(read_bytes(&offset_overflows, 0), Err(WireError::Malformed(_))));
+        assert!(matches!(read_string_array(&offset_overflows, 0), Err(WireError::Malformed(_))));
+    }
+}
@@ -0,0 +1,214 @@
+// Wire format shared by the Injective vault and the ICP canister
+// Every cross-chain payload is a versioned `Envelope` encoded as canonical JSON:
+// fields in declaration order, no whitespace, amounts as decimal strings.
+
+pub mod attribution;
+pub mod axelar;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Bump when a message changes shape; decoders reject other versions
+pub const WIRE_VERSION: u16 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Envelope<T> {
+    pub version: u16,
+    // Per-sender sequence number, used by the receiver for replay protection
+    pub nonce: u64,
+    pub message: T,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(nonce: u64, message: T) -> Self {
+        Self {
+            version: WIRE_VERSION,
+            nonce,
+            message,
+        }
+    }
+}
+
+// Injective vault -> ICP canister
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectiveToIcp {
+    // Skimmed yield (USDC, 6 decimals) bridged for `principal`
+    DepositYield {
+        principal: String,
+        #[serde(with = "amount")]
+        amount: u128,
+        // Merkle root of the per-depositor breakdown of this yield; see `attribution`
+        #[serde(default, skip_serializing_if = "Option::is_none", with = "attribution::hex_root")]
+        attribution_root: Option<attribution::Hash>,
+    },
+}
+
+// ICP canister -> Injective vault
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IcpToInjective {
+    Deposit {
+        user: String,
+        #[serde(with = "amount")]
+        amount: u128,
+    },
+    SkimYield {
+        recipient: String,
+        max_slippage_bps: Option<u64>,
+    },
+}
+
+// The vault `ExecuteMsg` variant that carries ICP messages. Only this variant is
+// mirrored here; the name must match the vault's `ExecuteMsg::ExecuteIcpMessage`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum InjectiveExecuteMsg {
+    ExecuteIcpMessage { envelope: Envelope<IcpToInjective> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WireError {
+    Malformed(String),
+    UnsupportedVersion(u16),
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::Malformed(reason) => write!(f, "malformed message: {}", reason),
+            WireError::UnsupportedVersion(version) => {
+                write!(f, "unsupported wire version {} (expected {})", version, WIRE_VERSION)
+            },
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+pub fn encode<T: Serialize>(envelope: &Envelope<T>) -> Vec<u8> {
+    serde_json::to_vec(envelope).expect("wire messages always serialize")
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<Envelope<T>, WireError> {
+    // Read the version first so a newer sender gets a precise error instead of a parse failure
+    #[derive(Deserialize)]
+    struct Version {
+        version: u16,
+    }
+    let probe: Version = serde_json::from_slice(bytes).map_err(|err| WireError::Malformed(err.to_string()))?;
+    check_version(probe.version)?;
+    serde_json::from_slice(bytes).map_err(|err| WireError::Malformed(err.to_string()))
+}
+
+// Helper: for receivers that deserialize an `Envelope` as part of a larger message
+pub fn check_version(version: u16) -> Result<(), WireError> {
+    if version != WIRE_VERSION {
+        return Err(WireError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+// u128 amounts travel as decimal strings, matching CosmWasm's `Uint128`
+mod amount {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deposit_yield_round_trips() {
+        let envelope = Envelope::new(
+            7,
+            InjectiveToIcp::DepositYield {
+                principal: "2vxsx-fae".to_string(),
+                amount: 1_500_000,
+                attribution_root: None,
+            },
+        );
+        let bytes = encode(&envelope);
+        assert_eq!(
+            String::from_utf8(bytes.clone()).unwrap(),
+            r#"{"version":1,"nonce":7,"message":{"deposit_yield":{"principal":"2vxsx-fae","amount":"1500000"}}}"#
+        );
+        assert_eq!(decode::<InjectiveToIcp>(&bytes).unwrap(), envelope);
+    }
+
+    #[test]
+    fn icp_actions_round_trip() {
+        for message in [
+            IcpToInjective::Deposit {
+                user: "inj1user".to_string(),
+                amount: u128::MAX,
+            },
+            IcpToInjective::SkimYield {
+                recipient: "2vxsx-fae".to_string(),
+                max_slippage_bps: Some(50),
+            },
+            IcpToInjective::SkimYield {
+                recipient: "2vxsx-fae".to_string(),
+                max_slippage_bps: None,
+            },
+        ] {
+            let envelope = Envelope::new(1, message);
+            assert_eq!(decode::<IcpToInjective>(&encode(&envelope)).unwrap(), envelope);
+        }
+    }
+
+    #[test]
+    fn execute_msg_round_trips() {
+        let msg = InjectiveExecuteMsg::ExecuteIcpMessage {
+            envelope: Envelope::new(
+                3,
+                IcpToInjective::SkimYield {
+                    recipient: "2vxsx-fae".to_string(),
+                    max_slippage_bps: None,
+                },
+            ),
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            json,
+            r#"{"ExecuteIcpMessage":{"envelope":{"version":1,"nonce":3,"message":{"skim_yield":{"recipient":"2vxsx-fae","max_slippage_bps":null}}}}}"#
+        );
+        assert_eq!(serde_json::from_str::<InjectiveExecuteMsg>(&json).unwrap(), msg);
+    }
+
+    #[test]
+    fn attribution_root_travels_as_hex() {
+        let envelope = Envelope::new(
+            8,
+            InjectiveToIcp::DepositYield {
+                principal: "2vxsx-fae".to_string(),
+                amount: 10,
+                attribution_root: Some([0xab; 32]),
+            },
+        );
+        let json = String::from_utf8(encode(&envelope)).unwrap();
+        assert!(json.contains(&format!(r#""attribution_root":"{}""#, "ab".repeat(32))));
+        assert_eq!(decode::<InjectiveToIcp>(json.as_bytes()).unwrap(), envelope);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let bytes = br#"{"version":2,"nonce":1,"message":{"deposit_yield":{"principal":"p","amount":"1"}}}"#;
+        assert_eq!(decode::<InjectiveToIcp>(bytes), Err(WireError::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn rejects_numeric_amounts() {
+        let bytes = br#"{"version":1,"nonce":1,"message":{"deposit_yield":{"principal":"p","amount":1}}}"#;
+        assert!(matches!(decode::<InjectiveToIcp>(bytes), Err(WireError::Malformed(_))));
+    }
+}